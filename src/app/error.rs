@@ -12,13 +12,18 @@ pub enum AppRunError {
 
     #[error("health check failed")]
     HealthCheck(String),
+
+    #[error("invalid configuration")]
+    InvalidConfig(String),
 }
 
 impl From<AppRunError> for AppError {
     fn from(err: AppRunError) -> Self {
         match err {
             AppRunError::RuntimeInit(e) => AppError::from(ConfigError::Io(e)),
-            AppRunError::LoggingInit(msg) | AppRunError::HealthCheck(msg) => {
+            AppRunError::LoggingInit(msg)
+            | AppRunError::HealthCheck(msg)
+            | AppRunError::InvalidConfig(msg) => {
                 AppError::from(ConfigError::Io(std::io::Error::other(msg)))
             }
         }
@@ -11,7 +11,7 @@ use app_base::{
 };
 use tracing::info;
 
-use crate::{cli::Cli, config::AppConfig, logging};
+use crate::{cli::Cli, config::AppConfig, logging, state::State};
 use error::AppRunError;
 
 pub struct MotMot;
@@ -45,7 +45,13 @@ impl App for MotMot {
 
                 health::run(&config).await?;
 
-                let handles = servers::start_servers(config.clone(), ctx.signals.clone()).await;
+                let state = Arc::new(State::new(&config).map_err(|e| {
+                    tracing::error!(error = %e, "invalid_config");
+                    AppRunError::InvalidConfig(format!("invalid_config: {e}"))
+                })?);
+
+                let handles =
+                    servers::start_servers(config.clone(), state, ctx.signals.clone()).await;
 
                 tokio::select! {
                     _ = ctx.signals.wait_shutdown() => {
@@ -7,9 +7,11 @@ use app_base::signals::SignalHandler;
 
 use crate::config::AppConfig;
 use crate::net::run_server;
+use crate::state::State;
 
 pub async fn start_servers(
     config: Arc<AppConfig>,
+    state: Arc<State>,
     signals: Arc<SignalHandler>,
 ) -> Vec<(String, JoinHandle<Result<(), ConnectionError>>)> {
     let mut handles = Vec::new();
@@ -21,6 +23,7 @@ pub async fn start_servers(
             port = server_config.port,
             webtransport = server_config.webtransport,
             routes = server_config.routes.len(),
+            hosts = server_config.hosts.len(),
             "server_starting"
         );
 
@@ -28,6 +31,7 @@ pub async fn start_servers(
         let server_name = name.clone();
         let server_name_for_task = server_name.clone();
         let config_for_task = config.clone();
+        let state_for_task = state.clone();
         let signals_for_task = Arc::clone(&signals);
 
         let span = tracing::info_span!("server", server = %server_name);
@@ -36,9 +40,11 @@ pub async fn start_servers(
             async move {
                 run_server(
                     config_for_task,
+                    state_for_task,
                     server_name_for_task,
                     signals_for_task,
-                ).await
+                )
+                .await
             }
             .instrument(span),
         );
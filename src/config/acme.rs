@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// certificates from an acme (rfc 8555) ca for servers listing `acme` names;
+// needs the `acme` feature
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Acme {
+    #[serde(default = "Acme::default_directory")]
+    pub directory: String,
+
+    // root the directory is served with, for test cas such as pebble
+    #[serde(default)]
+    pub ca_root: Option<PathBuf>,
+
+    // e.g. "mailto:admin@example.com"
+    #[serde(default)]
+    pub contact: Vec<String>,
+
+    // no account is made until the ca's terms of service are accepted
+    #[serde(default)]
+    pub accept_terms: bool,
+
+    // account key, and a directory per server with its certificate
+    #[serde(default = "Acme::default_state_dir")]
+    pub state_dir: PathBuf,
+
+    #[serde(default)]
+    pub challenge: AcmeChallenge,
+
+    // tcp port tls-alpn-01 is answered on; the server's own port when left out
+    #[serde(default)]
+    pub tls_alpn_port: Option<u16>,
+
+    // tcp port http-01 is answered on, for every server
+    #[serde(default = "Acme::default_http_port")]
+    pub http_port: u16,
+
+    // certificates expiring sooner than this are renewed
+    #[serde(default = "Acme::default_renew_before_days")]
+    pub renew_before_days: u64,
+
+    #[serde(default = "Acme::default_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AcmeChallenge {
+    // a tls handshake over tcp on the server's address
+    #[default]
+    TlsAlpn01,
+    // a plain http request to /.well-known/acme-challenge/
+    Http01,
+}
+
+impl Acme {
+    pub fn default_directory() -> String {
+        "https://acme-v02.api.letsencrypt.org/directory".to_string()
+    }
+
+    pub fn default_state_dir() -> PathBuf {
+        PathBuf::from("/var/lib/motmot/acme")
+    }
+
+    pub fn default_http_port() -> u16 {
+        80
+    }
+
+    pub fn default_renew_before_days() -> u64 {
+        30
+    }
+
+    pub fn default_check_interval_secs() -> u64 {
+        12 * 60 * 60
+    }
+
+    /// certificate chain and key issued for `server_name`
+    pub fn cert_paths(&self, server_name: &str) -> (PathBuf, PathBuf) {
+        let dir = self.state_dir.join(server_name);
+        (dir.join("cert.pem"), dir.join("key.pem"))
+    }
+}
+
+impl Default for Acme {
+    fn default() -> Self {
+        Self {
+            directory: Self::default_directory(),
+            ca_root: None,
+            contact: Vec::new(),
+            accept_terms: false,
+            state_dir: Self::default_state_dir(),
+            challenge: AcmeChallenge::default(),
+            tls_alpn_port: None,
+            http_port: Self::default_http_port(),
+            renew_before_days: Self::default_renew_before_days(),
+            check_interval_secs: Self::default_check_interval_secs(),
+        }
+    }
+}
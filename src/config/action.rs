@@ -5,13 +5,33 @@ use std::path::PathBuf;
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Action {
     Static {
+        // a file or a directory; may use route parameters, e.g. /var/www/users/{id}.html.
+        // a directory serves the rest of a prefix or wildcard route below it
         path: PathBuf,
         #[serde(default)]
         cache: bool,
+        // tried in order when a directory is requested
+        #[serde(default = "default_index")]
+        index: Vec<String>,
+        #[serde(default)]
+        autoindex: Autoindex,
+        // sent for every file instead of the type from the extension table
+        #[serde(default)]
+        content_type: Option<String>,
     },
 
     Proxy {
+        // name of an `upstreams` group, or a plain http://host:port[/prefix] uri
         upstream: String,
+        #[serde(default)]
+        cache: bool,
+        // upstream path template using route parameters, e.g. /v2/{rest};
+        // the query string is kept
+        #[serde(default)]
+        rewrite: Option<String>,
+        // how long the upstream gets to start its response
+        #[serde(default = "default_proxy_timeout")]
+        timeout_secs: u64,
     },
 
     Response {
@@ -21,12 +41,49 @@ pub enum Action {
         status: u16,
     },
 
+    Redirect {
+        // target url; may use route parameters like {id} and variables like
+        // $uri, $args, $is_args, $request_uri and $host
+        to: String,
+        // 301, 302, 303, 307 or 308
+        #[serde(default = "default_redirect_status")]
+        status: u16,
+    },
+
     Script {
         script: PathBuf,
         interpreter: String,
+        #[serde(default = "default_script_timeout")]
+        timeout_secs: u64,
     },
 }
 
+/// directory listing for directories without an index file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Autoindex {
+    #[default]
+    Off,
+    Html,
+    Json,
+}
+
+fn default_index() -> Vec<String> {
+    vec!["index.html".to_string()]
+}
+
 fn default_status_ok() -> u16 {
     200
 }
+
+fn default_redirect_status() -> u16 {
+    302
+}
+
+fn default_proxy_timeout() -> u64 {
+    60
+}
+
+fn default_script_timeout() -> u64 {
+    30
+}
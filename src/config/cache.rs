@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+// limits of the shared in-memory response cache
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cache {
+    #[serde(default = "Cache::default_max_size")]
+    pub max_size: u64,
+
+    #[serde(default = "Cache::default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Cache {
+    pub fn default_max_size() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    pub fn default_max_entries() -> usize {
+        10_000
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            max_size: Self::default_max_size(),
+            max_entries: Self::default_max_entries(),
+        }
+    }
+}
+
+// how a route's cacheable actions (`cache = true`) use the cache
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteCache {
+    // used when the response carries no max-age / s-maxage
+    #[serde(default = "RouteCache::default_ttl_secs")]
+    pub ttl_secs: u64,
+
+    // bigger responses are served but never stored
+    #[serde(default = "RouteCache::default_max_entry_size")]
+    pub max_entry_size: u64,
+
+    // honour cache-control (no-store, private, max-age, s-maxage)
+    #[serde(default = "default_true")]
+    pub respect_cache_control: bool,
+}
+
+impl RouteCache {
+    pub fn default_ttl_secs() -> u64 {
+        60
+    }
+
+    pub fn default_max_entry_size() -> u64 {
+        1024 * 1024
+    }
+}
+
+impl Default for RouteCache {
+    fn default() -> Self {
+        Self {
+            ttl_secs: Self::default_ttl_secs(),
+            max_entry_size: Self::default_max_entry_size(),
+            respect_cache_control: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
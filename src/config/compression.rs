@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+// content encodings for responses of one server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Compression {
+    // compress responses on the fly; needs the `compression` feature
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    // serve file.br / file.zst / file.gz next to a static file when the client takes it
+    #[serde(default = "default_true")]
+    pub precompressed: bool,
+
+    // smaller bodies are sent as they are
+    #[serde(default = "Compression::default_min_size")]
+    pub min_size: u64,
+
+    // media types compressed on the fly; "text/*" matches every text type
+    #[serde(default = "Compression::default_types")]
+    pub types: Vec<String>,
+
+    #[serde(default = "Compression::default_gzip_level")]
+    pub gzip_level: i32,
+
+    #[serde(default = "Compression::default_brotli_level")]
+    pub brotli_level: i32,
+
+    #[serde(default = "Compression::default_zstd_level")]
+    pub zstd_level: i32,
+}
+
+impl Compression {
+    pub fn default_min_size() -> u64 {
+        1024
+    }
+
+    pub fn default_types() -> Vec<String> {
+        [
+            "text/*",
+            "application/javascript",
+            "application/json",
+            "application/manifest+json",
+            "application/wasm",
+            "application/xml",
+            "image/svg+xml",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    pub fn default_gzip_level() -> i32 {
+        6
+    }
+
+    // higher brotli levels are too slow for compressing per request
+    pub fn default_brotli_level() -> i32 {
+        4
+    }
+
+    pub fn default_zstd_level() -> i32 {
+        3
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            precompressed: true,
+            min_size: Self::default_min_size(),
+            types: Self::default_types(),
+            gzip_level: Self::default_gzip_level(),
+            brotli_level: Self::default_brotli_level(),
+            zstd_level: Self::default_zstd_level(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
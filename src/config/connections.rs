@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+// how many connections a server takes, and when it makes clients prove their address
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Connections {
+    // open connections at once; 0 for no limit. more are refused
+    #[serde(default)]
+    pub max_connections: usize,
+
+    // the same, per client address block (see the prefixes below). an address
+    // is only counted once proven, so setting this sends every new one a retry
+    #[serde(default)]
+    pub max_per_client: usize,
+
+    // clients in the same block count as one: /32 is a single ipv4 address,
+    // /64 a typical ipv6 subnet handed to one site
+    #[serde(default = "Connections::default_client_prefix_v4")]
+    pub client_prefix_v4: u8,
+
+    #[serde(default = "Connections::default_client_prefix_v6")]
+    pub client_prefix_v6: u8,
+
+    // new connections per second above which unvalidated addresses get a
+    // stateless retry first, costing spoofed floods a round trip. 0 always retries.
+    // they get one regardless once the server is 3/4 full
+    #[serde(default)]
+    pub retry_above_per_sec: Option<u32>,
+}
+
+impl Connections {
+    pub fn default_client_prefix_v4() -> u8 {
+        32
+    }
+
+    pub fn default_client_prefix_v6() -> u8 {
+        64
+    }
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self {
+            max_connections: 0,
+            max_per_client: 0,
+            client_prefix_v4: Self::default_client_prefix_v4(),
+            client_prefix_v6: Self::default_client_prefix_v6(),
+            retry_above_per_sec: None,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// header rewriting; values may use variables such as $remote_addr, see `http::headers`.
+// server rules apply first, then route rules; within a block remove, set, then add.
+// headers that frame the message, such as content-length or connection, can't be touched
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HeaderRules {
+    // name and value pairs, appended next to any values already there; a name
+    // may come more than once, e.g. for several Link headers
+    #[serde(default)]
+    pub add_headers: Vec<(String, String)>,
+
+    // replace any values already there
+    #[serde(default)]
+    pub set_headers: HashMap<String, String>,
+
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+}
@@ -0,0 +1,19 @@
+use super::{RouteConfig, ServerTlsConf, StandardResponses};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// one hostname served on a server's socket, keyed by name in `Server.hosts`.
+// `*.example.com` keys cover every name one label below example.com.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VirtualHost {
+    // presented to clients sending this name as sni; the server's otherwise
+    #[serde(default)]
+    pub tls: Option<ServerTlsConf>,
+
+    #[serde(default)]
+    pub routes: HashMap<String, RouteConfig>,
+
+    // the server's are used when left out
+    #[serde(default)]
+    pub standard: Option<StandardResponses>,
+}
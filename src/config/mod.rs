@@ -1,16 +1,40 @@
+pub mod acme;
 pub mod action;
+pub mod cache;
+pub mod compression;
+pub mod connections;
+pub mod headers;
 pub mod health;
+pub mod host;
 pub mod logging;
+pub mod ratelimit;
 pub mod route;
+pub mod scripting;
 pub mod server;
 pub mod standard;
+pub mod tls;
+pub mod transport;
+pub mod upstream;
+pub mod validators;
 
-pub use action::Action;
+pub use acme::Acme;
+pub use action::{Action, Autoindex};
+pub use cache::Cache;
+pub use compression::Compression;
+pub use connections::Connections;
+pub use headers::HeaderRules;
 pub use health::Health;
+pub use host::VirtualHost;
 pub use logging::Logging;
+pub use ratelimit::{RateLimit, RateLimits};
 pub use route::RouteConfig;
+pub use scripting::Scripting;
 pub use server::Server;
 pub use standard::StandardResponses;
+pub use tls::ServerTlsConf;
+pub use transport::Transport;
+pub use upstream::Upstream;
+pub use validators::Validators;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -22,6 +46,25 @@ pub struct AppConfig {
 
     #[serde(default)]
     pub health: Health,
+
+    #[serde(default)]
+    pub upstreams: HashMap<String, Upstream>,
+
+    #[serde(default)]
+    pub cache: Cache,
+
+    #[serde(default)]
+    pub scripting: Scripting,
+
+    // extension (without the dot) to media type; adds to or replaces the built-in table
+    #[serde(default)]
+    pub mime_types: HashMap<String, String>,
+
+    #[serde(default)]
+    pub acme: Acme,
+
+    #[serde(default)]
+    pub rate_limits: RateLimits,
 }
 
 impl Default for AppConfig {
@@ -37,11 +80,28 @@ impl Default for AppConfig {
             Action::Static {
                 path: data_dir.join("index.html"),
                 cache: false, // change to true when cache get well-implemented.
+                index: vec!["index.html".to_string()],
+                autoindex: Autoindex::Off,
+                content_type: None,
             },
         );
 
         let mut routes = std::collections::HashMap::new();
-        routes.insert("/".to_string(), RouteConfig { methods });
+        routes.insert(
+            "/".to_string(),
+            RouteConfig {
+                methods,
+                prefix: false,
+                cache: cache::RouteCache::default(),
+                validators: Validators::default(),
+                max_body_size: None,
+                headers: HeaderRules::default(),
+                upstream_headers: HeaderRules::default(),
+                require_client: Vec::new(),
+                replay_safe: None,
+                rate_limit: None,
+            },
+        );
 
         let mut servers = std::collections::HashMap::new();
         servers.insert(
@@ -53,6 +113,16 @@ impl Default for AppConfig {
                 webtransport: false,
                 routes,
                 standard: standard::StandardResponses::default(),
+                compression: Compression::default(),
+                max_body_size: Server::default_max_body_size(),
+                headers: HeaderRules::default(),
+                upstream_headers: HeaderRules::default(),
+                hosts: HashMap::new(),
+                default_host: None,
+                acme: Vec::new(),
+                transport: Transport::default(),
+                connections: Connections::default(),
+                rate_limit: None,
             },
         );
 
@@ -63,6 +133,12 @@ impl Default for AppConfig {
                 file: Some(log_dir.join("motmot.log")),
             },
             health: health::Health::default(),
+            upstreams: HashMap::new(),
+            cache: Cache::default(),
+            scripting: Scripting::default(),
+            mime_types: HashMap::new(),
+            acme: Acme::default(),
+            rate_limits: RateLimits::default(),
         }
     }
 }
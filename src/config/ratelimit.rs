@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+// the shared store of rate limit buckets
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RateLimits {
+    // clients tracked at once across every limit. past that, a new client takes
+    // the place of one whose bucket has refilled, or is refused until one has
+    #[serde(default = "RateLimits::default_max_keys")]
+    pub max_keys: usize,
+}
+
+impl RateLimits {
+    pub fn default_max_keys() -> usize {
+        100_000
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            max_keys: Self::default_max_keys(),
+        }
+    }
+}
+
+// a token bucket: `requests` per `period_secs`, refilled evenly, with room for
+// `burst` at once. needs the `ratelimit` feature
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+
+    #[serde(default = "RateLimit::default_period_secs")]
+    pub period_secs: u64,
+
+    // defaults to `requests`
+    #[serde(default)]
+    pub burst: Option<u32>,
+
+    // who shares a bucket
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+impl RateLimit {
+    pub fn default_period_secs() -> u64 {
+        60
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitKey {
+    // each client address
+    #[default]
+    Ip,
+    // ipv6 clients by /64, the block a single site usually gets; ipv4 by address
+    Prefix,
+    // the value of a request header, e.g. an api key; the address without one.
+    // only for values checked before they get here, such as keys a gateway in
+    // front verified: every new value a client makes up is a fresh bucket
+    Header(String),
+    // everyone: one bucket for the whole server or route
+    Route,
+}
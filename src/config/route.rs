@@ -1,4 +1,8 @@
 use super::Action;
+use super::cache::RouteCache;
+use super::headers::HeaderRules;
+use super::ratelimit::RateLimit;
+use super::validators::Validators;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -7,4 +11,42 @@ pub struct RouteConfig {
     // map http methods to an action
     #[serde(default)]
     pub methods: HashMap<String, Action>,
+
+    // also match every path below this one; see `http::router` for key syntax
+    #[serde(default)]
+    pub prefix: bool,
+
+    #[serde(default)]
+    pub cache: RouteCache,
+
+    #[serde(default)]
+    pub validators: Validators,
+
+    // overrides the server's max_body_size for this route
+    #[serde(default)]
+    pub max_body_size: Option<u64>,
+
+    // add_headers / set_headers / remove_headers for responses of this route
+    #[serde(default, flatten)]
+    pub headers: HeaderRules,
+
+    // the same, for requests proxied upstream from this route
+    #[serde(default)]
+    pub upstream_headers: HeaderRules,
+
+    // client certificates allowed here, by subject ("CN=ops,O=Example") or
+    // subject alternative name ("DNS:ops.example.com", "email:ops@example.com").
+    // empty lets every client in; otherwise a verified certificate must match one.
+    #[serde(default)]
+    pub require_client: Vec<String>,
+
+    // whether 0-rtt requests, which can be replayed, are served: true for
+    // every method, false for none. unset allows GET and HEAD only.
+    // the rest are answered 425 so the client retries after the handshake.
+    #[serde(default)]
+    pub replay_safe: Option<bool>,
+
+    // requests allowed per client on this route, on top of the server's limit
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
 }
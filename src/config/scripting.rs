@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Scripting {
+    // scripts running at once, across all servers; others wait for a slot
+    #[serde(default = "Scripting::default_max_processes")]
+    pub max_processes: usize,
+}
+
+impl Scripting {
+    pub fn default_max_processes() -> usize {
+        16
+    }
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        Self {
+            max_processes: Self::default_max_processes(),
+        }
+    }
+}
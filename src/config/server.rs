@@ -1,4 +1,7 @@
-use super::{RouteConfig, StandardResponses};
+use super::{
+    Compression, Connections, HeaderRules, RateLimit, RouteConfig, ServerTlsConf,
+    StandardResponses, Transport, VirtualHost,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -18,10 +21,52 @@ pub struct Server {
 
     #[serde(default)]
     pub standard: StandardResponses,
+
+    #[serde(default)]
+    pub compression: Compression,
+
+    // largest request body accepted, in bytes; 0 for no limit. routes may override it
+    #[serde(default = "Server::default_max_body_size")]
+    pub max_body_size: u64,
+
+    // add_headers / set_headers / remove_headers for every response
+    #[serde(default, flatten)]
+    pub headers: HeaderRules,
+
+    // the same, for requests proxied upstream
+    #[serde(default)]
+    pub upstream_headers: HeaderRules,
+
+    // hostnames sharing this socket, chosen by :authority and sni. when there
+    // are none, `routes` answer every request
+    #[serde(default)]
+    pub hosts: HashMap<String, VirtualHost>,
+
+    // key in `hosts` answering names none of them match; without one those
+    // requests get standard.misdirected_request
+    #[serde(default)]
+    pub default_host: Option<String>,
+
+    // names to get a certificate for from `AppConfig.acme`. it is presented for
+    // them, and for everything else when there is no `tls`
+    #[serde(default)]
+    pub acme: Vec<String>,
+
+    // quic tuning: timeouts, stream limits, windows, congestion control
+    #[serde(default)]
+    pub transport: Transport,
+
+    // connection limits and address validation under load
+    #[serde(default)]
+    pub connections: Connections,
+
+    // requests allowed per client across the server; routes may add their own
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ServerTlsConf {
-    pub cert: std::path::PathBuf,
-    pub key: std::path::PathBuf,
+impl Server {
+    pub fn default_max_body_size() -> u64 {
+        1024 * 1024
+    }
 }
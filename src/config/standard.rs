@@ -6,6 +6,77 @@ pub struct StandardResponses {
     pub not_found: Action,
     pub method_not_allowed: Action,
     pub internal_error: Action,
+
+    #[serde(default = "StandardResponses::default_payload_too_large")]
+    pub payload_too_large: Action,
+
+    #[serde(default = "StandardResponses::default_misdirected_request")]
+    pub misdirected_request: Action,
+
+    #[serde(default = "StandardResponses::default_forbidden")]
+    pub forbidden: Action,
+
+    #[serde(default = "StandardResponses::default_too_early")]
+    pub too_early: Action,
+
+    #[serde(default = "StandardResponses::default_too_many_requests")]
+    pub too_many_requests: Action,
+}
+
+impl StandardResponses {
+    /// every response, by name
+    pub fn actions(&self) -> [(&'static str, &Action); 8] {
+        [
+            ("not_found", &self.not_found),
+            ("method_not_allowed", &self.method_not_allowed),
+            ("internal_error", &self.internal_error),
+            ("payload_too_large", &self.payload_too_large),
+            ("misdirected_request", &self.misdirected_request),
+            ("forbidden", &self.forbidden),
+            ("too_early", &self.too_early),
+            ("too_many_requests", &self.too_many_requests),
+        ]
+    }
+
+    pub fn default_payload_too_large() -> Action {
+        Action::Response {
+            body: "Content Too Large".into(),
+            content_type: "text/plain; charset=utf-8".into(),
+            status: 413,
+        }
+    }
+
+    pub fn default_misdirected_request() -> Action {
+        Action::Response {
+            body: "Misdirected Request".into(),
+            content_type: "text/plain; charset=utf-8".into(),
+            status: 421,
+        }
+    }
+
+    pub fn default_forbidden() -> Action {
+        Action::Response {
+            body: "Forbidden".into(),
+            content_type: "text/plain; charset=utf-8".into(),
+            status: 403,
+        }
+    }
+
+    pub fn default_too_early() -> Action {
+        Action::Response {
+            body: "Too Early".into(),
+            content_type: "text/plain; charset=utf-8".into(),
+            status: 425,
+        }
+    }
+
+    pub fn default_too_many_requests() -> Action {
+        Action::Response {
+            body: "Too Many Requests".into(),
+            content_type: "text/plain; charset=utf-8".into(),
+            status: 429,
+        }
+    }
 }
 
 impl Default for StandardResponses {
@@ -26,6 +97,11 @@ impl Default for StandardResponses {
                 content_type: "text/plain; charset=utf-8".into(),
                 status: 500,
             },
+            payload_too_large: Self::default_payload_too_large(),
+            misdirected_request: Self::default_misdirected_request(),
+            forbidden: Self::default_forbidden(),
+            too_early: Self::default_too_early(),
+            too_many_requests: Self::default_too_many_requests(),
         }
     }
 }
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// certificates of a server or virtual host. the sni fallbacks, reloading,
+// client certificates and the handshake policy are per socket, so only the
+// server's count
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ServerTlsConf {
+    // presented when no entry of `certificates` names the sni
+    pub cert: PathBuf,
+    pub key: PathBuf,
+
+    // more certificates on the same socket, chosen by sni
+    #[serde(default)]
+    pub certificates: Vec<TlsCertificate>,
+
+    // handshakes without sni
+    #[serde(default)]
+    pub missing_sni: SniFallback,
+
+    // handshakes naming something no certificate is listed for
+    #[serde(default)]
+    pub unknown_sni: SniFallback,
+
+    // how often the files, the virtual hosts' included, are checked for changes;
+    // 0 to reload on SIGUSR1 only
+    #[serde(default = "ServerTlsConf::default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+
+    // whether clients are asked for a certificate, see `ClientAuth`
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+
+    // pem bundle of the cas client certificates have to chain to
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+
+    // pem crls; client certificates revoked in them are refused
+    #[serde(default)]
+    pub client_crls: Vec<PathBuf>,
+
+    // quic runs over tls 1.3 only, so this can't name anything else
+    #[serde(default = "ServerTlsConf::default_versions")]
+    pub versions: Vec<TlsVersion>,
+
+    // names as in the rfcs, e.g. "TLS13_AES_128_GCM_SHA256", most preferred
+    // first; empty for the defaults. quic needs TLS13_AES_128_GCM_SHA256.
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+
+    // e.g. "X25519MLKEM768", "X25519", "secp256r1", most preferred first;
+    // empty for the defaults, which lead with the hybrid post-quantum group
+    #[serde(default)]
+    pub kx_groups: Vec<String>,
+
+    // stateless resumption; keys rotate in memory unless `ticket_key_file` is set
+    #[serde(default)]
+    pub session_tickets: bool,
+
+    // hex encoded 32 byte keys, one per line: the first encrypts, all of them
+    // decrypt. shared by instances behind one address; rotate by putting a new
+    // key first. re-read with the certificates when it changes, see
+    // `reload_interval_secs`.
+    #[serde(default)]
+    pub ticket_key_file: Option<PathBuf>,
+
+    // how long clients may keep tickets from `ticket_key_file`
+    #[serde(default = "ServerTlsConf::default_ticket_lifetime_secs")]
+    pub ticket_lifetime_secs: u32,
+
+    // sessions remembered for stateful resumption; 0 turns it off
+    #[serde(default = "ServerTlsConf::default_session_cache_size")]
+    pub session_cache_size: usize,
+
+    // 0-rtt for resumed sessions; 0 keeps it off. quic has no tls limit on
+    // early data, so this caps the body of each request sent in it instead.
+    // which requests are answered early is up to routes, see `replay_safe`.
+    #[serde(default)]
+    pub max_early_data_size: u32,
+}
+
+impl ServerTlsConf {
+    pub fn default_reload_interval_secs() -> u64 {
+        60
+    }
+
+    pub fn default_versions() -> Vec<TlsVersion> {
+        vec![TlsVersion::Tls13]
+    }
+
+    pub fn default_ticket_lifetime_secs() -> u32 {
+        12 * 60 * 60
+    }
+
+    pub fn default_session_cache_size() -> usize {
+        256
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TlsCertificate {
+    // sni names this certificate is presented for; "*.example.com" covers one label
+    pub names: Vec<String>,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// what a handshake no listed certificate is for gets
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SniFallback {
+    // the default certificate
+    #[default]
+    Default,
+    // a failed handshake
+    Reject,
+}
+
+/// client certificate authentication (mutual tls)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+    #[default]
+    Off,
+    // asked for and verified when sent; routes decide what they need
+    Optional,
+    // handshakes without a valid one fail
+    Required,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+// quic transport settings of one server; unset values keep quinn's defaults.
+// bulk downloads want large windows, mobile clients short keep-alives.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Transport {
+    // connections silent this long are closed; 0 never times out
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+
+    // ping idle connections this often to keep nat bindings and the idle timeout alive
+    #[serde(default)]
+    pub keep_alive_interval_secs: Option<u64>,
+
+    // streams a client may have open at once
+    #[serde(default)]
+    pub max_concurrent_bidi_streams: Option<u32>,
+
+    // at least 3, which http/3 itself takes
+    #[serde(default)]
+    pub max_concurrent_uni_streams: Option<u32>,
+
+    // bytes a client may send on one stream before it is read
+    #[serde(default)]
+    pub stream_receive_window: Option<u64>,
+
+    // the same, across every stream of a connection
+    #[serde(default)]
+    pub receive_window: Option<u64>,
+
+    // round trip assumed until one is measured
+    #[serde(default)]
+    pub initial_rtt_ms: Option<u64>,
+
+    // probe for packets larger than the 1200 byte minimum
+    #[serde(default = "default_true")]
+    pub mtu_discovery: bool,
+
+    #[serde(default)]
+    pub congestion: Congestion,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Congestion {
+    #[default]
+    Cubic,
+    NewReno,
+    // experimental in quinn
+    Bbr,
+}
+
+fn default_true() -> bool {
+    true
+}
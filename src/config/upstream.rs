@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+// named group of backends that proxy actions can refer to instead of a single uri
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Upstream {
+    pub backends: Vec<Backend>,
+
+    #[serde(default)]
+    pub balance: Balance,
+
+    // only used by `consistent_hash`
+    #[serde(default)]
+    pub hash_key: HashKey,
+
+    #[serde(default)]
+    pub health: UpstreamHealth,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Backend {
+    // http://host:port[/prefix], same format as a plain proxy upstream
+    pub address: String,
+
+    // 0 takes the backend out of rotation; at most 1000
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+// backends leave rotation after `max_fails` consecutive failures, either failed
+// probes or failed proxied requests, and come back after `recover_after` successes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpstreamHealth {
+    // active probing is off unless a path is set
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default = "UpstreamHealth::default_expected_status")]
+    pub expected_status: u16,
+
+    #[serde(default = "UpstreamHealth::default_interval_secs")]
+    pub interval_secs: u64,
+
+    #[serde(default = "UpstreamHealth::default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(default = "UpstreamHealth::default_max_fails")]
+    pub max_fails: u32,
+
+    #[serde(default = "UpstreamHealth::default_recover_after")]
+    pub recover_after: u32,
+
+    // without probing, how long a down backend waits before it gets traffic again
+    #[serde(default = "UpstreamHealth::default_fail_timeout_secs")]
+    pub fail_timeout_secs: u64,
+}
+
+impl UpstreamHealth {
+    pub fn default_expected_status() -> u16 {
+        200
+    }
+
+    pub fn default_interval_secs() -> u64 {
+        5
+    }
+
+    pub fn default_timeout_secs() -> u64 {
+        2
+    }
+
+    pub fn default_max_fails() -> u32 {
+        3
+    }
+
+    pub fn default_recover_after() -> u32 {
+        2
+    }
+
+    pub fn default_fail_timeout_secs() -> u64 {
+        10
+    }
+}
+
+impl Default for UpstreamHealth {
+    fn default() -> Self {
+        Self {
+            path: None,
+            expected_status: Self::default_expected_status(),
+            interval_secs: Self::default_interval_secs(),
+            timeout_secs: Self::default_timeout_secs(),
+            max_fails: Self::default_max_fails(),
+            recover_after: Self::default_recover_after(),
+            fail_timeout_secs: Self::default_fail_timeout_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    LeastConnections,
+    RandomTwoChoices,
+    ConsistentHash,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HashKey {
+    #[default]
+    ClientIp,
+    Header(String),
+}
+
+fn default_weight() -> u32 {
+    1
+}
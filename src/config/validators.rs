@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+// validators sent with static files, for conditional and range requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Validators {
+    #[serde(default)]
+    pub etag: ETag,
+
+    #[serde(default = "default_true")]
+    pub last_modified: bool,
+}
+
+impl Default for Validators {
+    fn default() -> Self {
+        Self {
+            etag: ETag::default(),
+            last_modified: true,
+        }
+    }
+}
+
+// entity tags are made from file metadata: modification time and size
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ETag {
+    Off,
+    // usable for If-Range; changes with every sub-second modification
+    #[default]
+    Strong,
+    // whole-second precision, never used for ranges
+    Weak,
+}
+
+fn default_true() -> bool {
+    true
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+use super::AcmeError;
+use crate::helpers::host;
+
+// alpn protocol of tls-alpn-01 (rfc 8737)
+const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+const HTTP01_PREFIX: &str = "/.well-known/acme-challenge/";
+
+// validators get this long to finish a handshake or request
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// responses to the challenges of orders in progress
+#[derive(Debug, Default)]
+pub(super) struct Challenges {
+    // http-01: token to key authorization
+    http: Mutex<HashMap<String, String>>,
+    // tls-alpn-01: name to its validation certificate
+    tls_alpn: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+/// a response put up for one challenge, to take down once the order is done
+pub(super) enum Registered {
+    Http(String),
+    TlsAlpn(String),
+}
+
+impl Challenges {
+    pub(super) fn add_http(&self, token: &str, key_authorization: &str) -> Registered {
+        lock(&self.http).insert(token.to_string(), key_authorization.to_string());
+        Registered::Http(token.to_string())
+    }
+
+    /// self-signed certificate for `name` carrying the key authorization digest
+    pub(super) fn add_tls_alpn(&self, name: &str, digest: &[u8]) -> Result<Registered, AcmeError> {
+        let mut params = rcgen::CertificateParams::new(vec![name.to_string()])?;
+        params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(digest)];
+        let key = rcgen::KeyPair::generate()?;
+        let cert = params.self_signed(&key)?;
+
+        let provider = rustls::crypto::CryptoProvider::get_default()
+            .ok_or_else(|| AcmeError::ValidationCert("no crypto provider installed".into()))?;
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+        let certified = CertifiedKey::from_der(vec![cert.der().clone()], key, provider)
+            .map_err(|e| AcmeError::ValidationCert(e.to_string()))?;
+
+        let name = host::normalize(name);
+        lock(&self.tls_alpn).insert(name.clone(), Arc::new(certified));
+        Ok(Registered::TlsAlpn(name))
+    }
+
+    pub(super) fn remove(&self, registered: &[Registered]) {
+        for registered in registered {
+            match registered {
+                Registered::Http(token) => {
+                    lock(&self.http).remove(token);
+                }
+                Registered::TlsAlpn(name) => {
+                    lock(&self.tls_alpn).remove(name);
+                }
+            }
+        }
+    }
+}
+
+// only acme-tls/1 is offered, so ordinary clients fail the handshake
+#[derive(Debug)]
+struct AlpnCerts(Arc<Challenges>);
+
+impl ResolvesServerCert for AlpnCerts {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = host::normalize(hello.server_name()?);
+        lock(&self.0.tls_alpn).get(&name).cloned()
+    }
+}
+
+/// answer tls-alpn-01 validation handshakes on tcp `addr`
+pub(super) async fn serve_tls_alpn(addr: SocketAddr, challenges: Arc<Challenges>) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(addr = %addr, error = %e, "acme_tls_alpn_bind_failed");
+            return;
+        }
+    };
+    info!(addr = %addr, "acme_tls_alpn_listening");
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(AlpnCerts(challenges)));
+    config.alpn_protocols = vec![ACME_TLS_ALPN.to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    loop {
+        let (tcp, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                debug!(error = %e, "acme_tls_alpn_accept_failed");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+
+        tokio::spawn(async move {
+            // the handshake is all a validator looks at
+            match time::timeout(VALIDATION_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(Ok(mut tls)) => {
+                    debug!(remote = %remote, "acme_tls_alpn_handshake");
+                    let _ = tls.shutdown().await;
+                }
+                Ok(Err(e)) => debug!(remote = %remote, error = %e, "acme_tls_alpn_failed"),
+                Err(_) => debug!(remote = %remote, "acme_tls_alpn_timeout"),
+            }
+        });
+    }
+}
+
+/// answer http-01 validation requests on tcp `port` of every address
+pub(super) async fn serve_http(port: u16, challenges: Arc<Challenges>) {
+    let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(addr = %addr, error = %e, "acme_http_bind_failed");
+            return;
+        }
+    };
+    info!(addr = %addr, "acme_http_listening");
+
+    loop {
+        let (tcp, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                debug!(error = %e, "acme_http_accept_failed");
+                continue;
+            }
+        };
+        let challenges = challenges.clone();
+
+        tokio::spawn(async move {
+            if time::timeout(VALIDATION_TIMEOUT, answer_http(tcp, &challenges))
+                .await
+                .is_err()
+            {
+                debug!(remote = %remote, "acme_http_timeout");
+            }
+        });
+    }
+}
+
+// one request per connection is plenty for a validator
+async fn answer_http(mut tcp: TcpStream, challenges: &Challenges) {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8 * 1024 {
+        match tcp.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let key_authorization = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => target
+            .strip_prefix(HTTP01_PREFIX)
+            .and_then(|token| lock(&challenges.http).get(token).cloned()),
+        _ => None,
+    };
+
+    let response = match key_authorization {
+        Some(body) => format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/octet-stream\r\n\
+             content-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        ),
+        None => {
+            "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+        }
+    };
+    let _ = tcp.write_all(response.as_bytes()).await;
+    let _ = tcp.shutdown().await;
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,30 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AcmeError {
+    #[error("acme request failed: {0}")]
+    Acme(#[from] instant_acme::Error),
+
+    #[error("failed to access '{path}': {source}")]
+    Io { path: String, source: io::Error },
+
+    #[error("invalid account credentials: {0}")]
+    Credentials(#[from] serde_json::Error),
+
+    #[error("failed to make a key or signing request: {0}")]
+    Key(#[from] rcgen::Error),
+
+    #[error("failed to make a validation certificate: {0}")]
+    ValidationCert(String),
+
+    #[error("terms of service not accepted, set acme.accept_terms")]
+    TermsNotAccepted,
+
+    #[error("the ca offers no {kind} challenge for '{name}'")]
+    NoChallenge { name: String, kind: String },
+
+    #[error("order for {names:?} ended {status}")]
+    Order { names: Vec<String>, status: String },
+}
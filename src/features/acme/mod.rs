@@ -0,0 +1,339 @@
+mod challenge;
+mod error;
+#[cfg(test)]
+mod tests;
+
+pub use error::AcmeError;
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, OrderStatus, RetryPolicy,
+};
+use rustls::pki_types::{CertificateDer, pem::PemObject};
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::acme::AcmeChallenge;
+use crate::config::{self, AppConfig, Server};
+use crate::helpers::x509;
+use crate::net::tls::{self, SniResolver};
+use challenge::{Challenges, Registered};
+
+// a failed order is tried again after this, or the check interval if sooner
+const RETRY_AFTER_FAILURE: Duration = Duration::from_secs(30 * 60);
+
+// how long the ca gets to validate an order, and then to issue it
+const VALIDATION_WAIT: Duration = Duration::from_secs(120);
+
+/// gets certificates for servers with `acme` names and renews them before they expire
+pub struct Acme {
+    inner: Arc<Inner>,
+    http: Option<JoinHandle<()>>,
+}
+
+struct Inner {
+    config: config::Acme,
+    // made or loaded by the first order
+    account: OnceCell<Account>,
+    challenges: Arc<Challenges>,
+}
+
+impl Acme {
+    pub fn new(config: &AppConfig) -> Self {
+        let inner = Arc::new(Inner {
+            config: config.acme.clone(),
+            account: OnceCell::new(),
+            challenges: Arc::new(Challenges::default()),
+        });
+
+        // one http-01 listener answers for every server
+        let wanted = config.servers.values().any(|s| !s.acme.is_empty());
+        let http = (wanted && config.acme.challenge == AcmeChallenge::Http01).then(|| {
+            tokio::spawn(challenge::serve_http(
+                config.acme.http_port,
+                inner.challenges.clone(),
+            ))
+        });
+
+        Self { inner, http }
+    }
+
+    /// keep the certificate of `server_name` issued and fresh, handing it to
+    /// `resolver` whenever it changes. None when the server has no `acme` names.
+    pub fn spawn(
+        &self,
+        config: Arc<AppConfig>,
+        server_name: String,
+        listen_addr: SocketAddr,
+        resolver: Arc<SniResolver>,
+    ) -> Option<JoinHandle<()>> {
+        if config.servers.get(&server_name)?.acme.is_empty() {
+            return None;
+        }
+
+        let inner = self.inner.clone();
+        Some(tokio::spawn(async move {
+            let tls_alpn = async {
+                if inner.config.challenge == AcmeChallenge::TlsAlpn01 {
+                    let port = inner.config.tls_alpn_port.unwrap_or(listen_addr.port());
+                    let addr = SocketAddr::new(listen_addr.ip(), port);
+                    challenge::serve_tls_alpn(addr, inner.challenges.clone()).await;
+                }
+            };
+
+            tokio::join!(tls_alpn, inner.renew_loop(&config, &server_name, &resolver));
+        }))
+    }
+}
+
+// listeners belong to one config generation; stop them when it is replaced
+impl Drop for Acme {
+    fn drop(&mut self) {
+        if let Some(http) = &self.http {
+            http.abort();
+        }
+    }
+}
+
+impl Inner {
+    async fn renew_loop(&self, config: &AppConfig, server_name: &str, resolver: &SniResolver) {
+        let Some(server) = config.servers.get(server_name) else {
+            return;
+        };
+        let interval = Duration::from_secs(self.config.check_interval_secs.max(60));
+
+        loop {
+            let wait = match self.renew(server_name, server).await {
+                Ok(false) => interval,
+                Ok(true) => {
+                    match tls::load_certs(config, server_name, server).await {
+                        Ok(certs) => {
+                            resolver.swap(certs);
+                            info!(server = server_name, "acme_certificate_installed");
+                        }
+                        Err(e) => warn!(server = server_name, error = %e, "acme_install_failed"),
+                    }
+                    interval
+                }
+                Err(e) => {
+                    warn!(server = server_name, names = ?server.acme, error = %e, "acme_order_failed");
+                    interval.min(RETRY_AFTER_FAILURE)
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // order a certificate when there is none for the configured names or it
+    // expires soon; true when a new one was written
+    async fn renew(&self, server_name: &str, server: &Server) -> Result<bool, AcmeError> {
+        let (cert_path, key_path) = self.config.cert_paths(server_name);
+        let names_path = cert_path.with_file_name("names");
+
+        let mut names = server.acme.clone();
+        names.sort();
+        names.dedup();
+        let names_list = names.join("\n");
+
+        let issued_for = tokio::fs::read_to_string(&names_path).await.ok();
+        let not_after = tokio::fs::read(&cert_path)
+            .await
+            .ok()
+            .and_then(|pem| CertificateDer::pem_slice_iter(&pem).next()?.ok())
+            .and_then(|cert| x509::not_after(&cert));
+
+        let renew_at = not_after.map(|t| {
+            t - TimeDelta::days(i64::try_from(self.config.renew_before_days).unwrap_or(0))
+        });
+        if issued_for.as_deref() == Some(names_list.as_str())
+            && let Some(renew_at) = renew_at
+            && renew_at > Utc::now()
+        {
+            debug!(server = server_name, renew_at = %renew_at, "acme_certificate_fresh");
+            return Ok(false);
+        }
+
+        info!(server = server_name, names = ?names, not_after = ?not_after, "acme_order_start");
+
+        let mut registered = Vec::new();
+        let result = self.order(&names, &mut registered).await;
+        self.challenges.remove(&registered);
+        let (chain, key) = result?;
+
+        // the key goes first: a new chain next to the old key would not load
+        write_private(&key_path, key.as_bytes()).await?;
+        write(&cert_path, chain.as_bytes()).await?;
+        write(&names_path, names_list.as_bytes()).await?;
+
+        info!(server = server_name, names = ?names, "acme_certificate_issued");
+        Ok(true)
+    }
+
+    // certificate chain and private key, both pem
+    async fn order(
+        &self,
+        names: &[String],
+        registered: &mut Vec<Registered>,
+    ) -> Result<(String, String), AcmeError> {
+        let account = self.account().await?;
+
+        let identifiers: Vec<Identifier> = names.iter().cloned().map(Identifier::Dns).collect();
+        let mut order = account.new_order(&NewOrder::new(&identifiers)).await?;
+
+        let kind = match self.config.challenge {
+            AcmeChallenge::TlsAlpn01 => ChallengeType::TlsAlpn01,
+            AcmeChallenge::Http01 => ChallengeType::Http01,
+        };
+
+        let mut authorizations = order.authorizations();
+        while let Some(authorization) = authorizations.next().await {
+            let mut authorization = authorization?;
+            if authorization.status != AuthorizationStatus::Pending {
+                continue;
+            }
+
+            let name = authorization.identifier().to_string();
+            let mut challenge =
+                authorization
+                    .challenge(kind.clone())
+                    .ok_or_else(|| AcmeError::NoChallenge {
+                        name: name.clone(),
+                        kind: format!("{kind:?}"),
+                    })?;
+
+            let key_authorization = challenge.key_authorization();
+            registered.push(match kind {
+                ChallengeType::Http01 => self
+                    .challenges
+                    .add_http(&challenge.token, key_authorization.as_str()),
+                _ => self
+                    .challenges
+                    .add_tls_alpn(&name, key_authorization.digest().as_ref())?,
+            });
+
+            challenge.set_ready().await?;
+            debug!(name = %name, "acme_challenge_ready");
+        }
+
+        let retries = RetryPolicy::new().timeout(VALIDATION_WAIT);
+        let status = order.poll_ready(&retries).await?;
+        if status != OrderStatus::Ready {
+            return Err(AcmeError::Order {
+                names: names.to_vec(),
+                status: format!("{status:?}"),
+            });
+        }
+
+        let key = rcgen::KeyPair::generate()?;
+        let mut params = rcgen::CertificateParams::new(names.to_vec())?;
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let csr = params.serialize_request(&key)?;
+
+        order.finalize_csr(csr.der()).await?;
+        let chain = order.poll_certificate(&retries).await?;
+
+        Ok((chain, key.serialize_pem()))
+    }
+
+    // the account in `state_dir`, made when there is none yet
+    async fn account(&self) -> Result<&Account, AcmeError> {
+        self.account
+            .get_or_try_init(|| async {
+                let path = self.config.state_dir.join("account.json");
+
+                let builder = match &self.config.ca_root {
+                    Some(root) => Account::builder_with_root(root)?,
+                    None => Account::builder()?,
+                };
+
+                match tokio::fs::read(&path).await {
+                    Ok(json) => {
+                        let credentials: AccountCredentials = serde_json::from_slice(&json)?;
+                        return Ok(builder.from_credentials(credentials).await?);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(io_error(&path, e)),
+                }
+
+                if !self.config.accept_terms {
+                    return Err(AcmeError::TermsNotAccepted);
+                }
+
+                let contact: Vec<&str> = self.config.contact.iter().map(String::as_str).collect();
+                let new_account = NewAccount {
+                    contact: &contact,
+                    terms_of_service_agreed: true,
+                    only_return_existing: false,
+                };
+                let (account, credentials) = builder
+                    .create(&new_account, self.config.directory.clone(), None)
+                    .await?;
+
+                write_private(&path, &serde_json::to_vec(&credentials)?).await?;
+                info!(directory = %self.config.directory, id = %account.id(), "acme_account_created");
+                Ok(account)
+            })
+            .await
+    }
+}
+
+async fn write(path: &Path, contents: &[u8]) -> Result<(), AcmeError> {
+    write_mode(path, contents, 0o644).await
+}
+
+// keys and account credentials are for the owner only
+async fn write_private(path: &Path, contents: &[u8]) -> Result<(), AcmeError> {
+    write_mode(path, contents, 0o600).await
+}
+
+// written next to `path` and renamed over it, so a crash or a full disk
+// never leaves a truncated file behind
+async fn write_mode(path: &Path, contents: &[u8], mode: u32) -> Result<(), AcmeError> {
+    use tokio::io::AsyncWriteExt;
+
+    let parent = path.parent().unwrap_or(Path::new("."));
+    tokio::fs::create_dir_all(parent)
+        .await
+        .map_err(|e| io_error(parent, e))?;
+
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    let temp = parent.join(name);
+
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(&temp)
+            .await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp, path).await
+    }
+    .await;
+
+    if let Err(e) = written {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(io_error(path, e));
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, source: io::Error) -> AcmeError {
+    AcmeError::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
@@ -0,0 +1,496 @@
+// a pebble-like stand-in ca on loopback: enough of rfc 8555 to take an order
+// from account to certificate, validating http-01 against our own responder
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rcgen::{
+    BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair, PKCS_ECDSA_P256_SHA256,
+    PublicKeyData, SignatureAlgorithm,
+};
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use x509_parser::prelude::{FromDer, X509CertificationRequest};
+
+use super::*;
+use crate::config::acme::AcmeChallenge;
+
+struct StubCa {
+    base: String,
+    http_port: u16,
+    issuer: Issuer<'static, KeyPair>,
+    root_pem: String,
+    state: Mutex<Orders>,
+}
+
+#[derive(Default)]
+struct Orders {
+    names: Vec<String>,
+    // by authorization index
+    valid: HashMap<usize, bool>,
+    chain: Option<String>,
+    nonce: u64,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    // decoded jws payload; empty for post-as-get
+    payload: Value,
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    location: Option<String>,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            location: None,
+            body: body.to_string(),
+        }
+    }
+
+    fn at(mut self, location: String) -> Self {
+        self.location = Some(location);
+        self
+    }
+}
+
+impl StubCa {
+    // the ca, serving https on loopback with a certificate from its own root
+    async fn start(http_port: u16) -> (Arc<Self>, PathBuf) {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let root = params.self_signed(&ca_key).unwrap();
+        let issuer = Issuer::new(params, ca_key);
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .signed_by(&server_key, &issuer)
+            .unwrap();
+
+        let mut tls = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![server_cert.der().clone()],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(server_key.serialize_der())),
+            )
+            .unwrap();
+        tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(tls));
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let ca = Arc::new(Self {
+            base: format!("https://localhost:{port}"),
+            http_port,
+            issuer,
+            root_pem: root.pem(),
+            state: Mutex::new(Orders::default()),
+        });
+
+        let serving = ca.clone();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let (ca, acceptor) = (serving.clone(), acceptor.clone());
+                tokio::spawn(async move {
+                    if let Ok(tls) = acceptor.accept(tcp).await {
+                        ca.serve(tls).await;
+                    }
+                });
+            }
+        });
+
+        let dir = temp_dir("ca");
+        let root_path = dir.join("root.pem");
+        std::fs::write(&root_path, &ca.root_pem).unwrap();
+        (ca, root_path)
+    }
+
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(&self, mut conn: S) {
+        let Some(request) = read_request(&mut conn).await else {
+            return;
+        };
+        let reply = self.answer(request).await;
+
+        let nonce = {
+            let mut state = self.state.lock().unwrap();
+            state.nonce += 1;
+            state.nonce
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} Stub\r\ncontent-type: {}\r\ncontent-length: {}\r\n\
+             replay-nonce: nonce-{nonce}\r\nconnection: close\r\n",
+            reply.status,
+            reply.content_type,
+            reply.body.len()
+        );
+        if let Some(location) = reply.location {
+            head.push_str(&format!("location: {location}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let _ = conn.write_all(head.as_bytes()).await;
+        let _ = conn.write_all(reply.body.as_bytes()).await;
+        let _ = conn.shutdown().await;
+    }
+
+    async fn answer(&self, request: Request) -> Reply {
+        let base = &self.base;
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["dir"]) => Reply::json(
+                200,
+                json!({
+                    "newNonce": format!("{base}/nonce"),
+                    "newAccount": format!("{base}/account"),
+                    "newOrder": format!("{base}/order"),
+                }),
+            ),
+            ("HEAD", ["nonce"]) => Reply {
+                status: 200,
+                content_type: "text/plain",
+                location: None,
+                body: String::new(),
+            },
+            ("POST", ["account"]) => {
+                Reply::json(201, json!({ "status": "valid" })).at(format!("{base}/account/1"))
+            }
+            ("POST", ["order"]) => {
+                let names = request.payload["identifiers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|id| id["value"].as_str().map(str::to_string))
+                    .collect();
+                *self.state.lock().unwrap() = Orders {
+                    names,
+                    ..Orders::default()
+                };
+                Reply::json(201, self.order()).at(format!("{base}/order/1"))
+            }
+            ("POST", ["order", "1"]) => Reply::json(200, self.order()),
+            ("POST", ["authz", index]) => {
+                let index = index.parse().unwrap_or(usize::MAX);
+                let state = self.state.lock().unwrap();
+                let Some(name) = state.names.get(index) else {
+                    return Reply::json(404, json!({ "type": "about:blank" }));
+                };
+                let status = if state.valid.get(&index) == Some(&true) {
+                    "valid"
+                } else {
+                    "pending"
+                };
+                Reply::json(
+                    200,
+                    json!({
+                        "status": status,
+                        "identifier": { "type": "dns", "value": name },
+                        "challenges": [self.challenge(index, status)],
+                    }),
+                )
+            }
+            ("POST", ["challenge", index]) => {
+                let index: usize = index.parse().unwrap_or(usize::MAX);
+                // what a validator does: fetch the token over plain http
+                let answer = http_get(
+                    self.http_port,
+                    &format!("/.well-known/acme-challenge/{}", token(index)),
+                )
+                .await;
+                let valid = answer.is_some_and(|body| {
+                    body.strip_prefix(&format!("{}.", token(index)))
+                        .is_some_and(|thumbprint| !thumbprint.is_empty())
+                });
+                self.state.lock().unwrap().valid.insert(index, valid);
+
+                let status = if valid { "valid" } else { "invalid" };
+                Reply::json(200, self.challenge(index, status))
+            }
+            ("POST", ["finalize"]) => {
+                let chain = request.payload["csr"]
+                    .as_str()
+                    .and_then(base64url)
+                    .and_then(|csr| self.issue(&csr));
+                let Some(chain) = chain else {
+                    return Reply::json(
+                        400,
+                        json!({ "type": "urn:ietf:params:acme:error:badCSR" }),
+                    );
+                };
+                self.state.lock().unwrap().chain = Some(chain);
+                Reply::json(200, self.order())
+            }
+            ("POST", ["cert"]) => Reply {
+                status: 200,
+                content_type: "application/pem-certificate-chain",
+                location: None,
+                body: self.state.lock().unwrap().chain.clone().unwrap_or_default(),
+            },
+            _ => Reply::json(404, json!({ "type": "about:blank" })),
+        }
+    }
+
+    fn order(&self) -> Value {
+        let base = &self.base;
+        let state = self.state.lock().unwrap();
+
+        let all_valid = (0..state.names.len()).all(|i| state.valid.get(&i) == Some(&true));
+        let status = match (&state.chain, all_valid) {
+            (Some(_), _) => "valid",
+            (None, true) => "ready",
+            (None, false) => "pending",
+        };
+
+        let mut order = json!({
+            "status": status,
+            "identifiers": state.names.iter().map(|n| json!({ "type": "dns", "value": n })).collect::<Vec<_>>(),
+            "authorizations": (0..state.names.len()).map(|i| format!("{base}/authz/{i}")).collect::<Vec<_>>(),
+            "finalize": format!("{base}/finalize"),
+        });
+        if state.chain.is_some() {
+            order["certificate"] = json!(format!("{base}/cert"));
+        }
+        order
+    }
+
+    fn challenge(&self, index: usize, status: &str) -> Value {
+        json!({
+            "type": "http-01",
+            "url": format!("{}/challenge/{index}", self.base),
+            "token": token(index),
+            "status": status,
+        })
+    }
+
+    // a certificate for the key in `csr` and the names of the order, then the root
+    fn issue(&self, csr: &[u8]) -> Option<String> {
+        let (_, csr) = X509CertificationRequest::from_der(csr).ok()?;
+        let key = CsrKey(
+            csr.certification_request_info
+                .subject_pki
+                .subject_public_key
+                .data
+                .to_vec(),
+        );
+
+        let names = self.state.lock().unwrap().names.clone();
+        let cert = CertificateParams::new(names)
+            .ok()?
+            .signed_by(&key, &self.issuer)
+            .ok()?;
+        Some(format!("{}{}", cert.pem(), self.root_pem))
+    }
+}
+
+// the public key of a signing request; rcgen makes p-256 keys by default
+struct CsrKey(Vec<u8>);
+
+impl PublicKeyData for CsrKey {
+    fn der_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn algorithm(&self) -> &'static SignatureAlgorithm {
+        &PKCS_ECDSA_P256_SHA256
+    }
+}
+
+fn token(index: usize) -> String {
+    format!("token-{index}")
+}
+
+async fn read_request<S: AsyncRead + Unpin>(conn: &mut S) -> Option<Request> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = conn.read(&mut buf).await.ok().filter(|n| *n > 0)?;
+        data.extend_from_slice(&buf[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split(' ');
+    let (method, path) = (request_line.next()?.to_string(), request_line.next()?);
+    let path = path.split_once("://").map_or(path, |(_, rest)| {
+        rest.find('/').map_or("/", |at| &rest[at..])
+    });
+
+    let len: usize = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+    let mut body = data[end..].to_vec();
+    while body.len() < len {
+        let n = conn.read(&mut buf).await.ok().filter(|n| *n > 0)?;
+        body.extend_from_slice(&buf[..n]);
+    }
+
+    // flattened jws: only the payload matters here, signatures aren't checked
+    let payload = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|jws| base64url(jws["payload"].as_str()?))
+        .and_then(|payload| serde_json::from_slice(&payload).ok())
+        .unwrap_or(Value::Null);
+
+    Some(Request {
+        method,
+        path: path.to_string(),
+        payload,
+    })
+}
+
+async fn http_get(port: u16, path: &str) -> Option<String> {
+    let mut tcp = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await.ok()?;
+    tcp.write_all(format!("GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n").as_bytes())
+        .await
+        .ok()?;
+    let mut response = String::new();
+    tcp.read_to_string(&mut response).await.ok()?;
+
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    head.starts_with("HTTP/1.1 200").then(|| body.to_string())
+}
+
+fn base64url(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn temp_dir(what: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "motmot-acme-{what}-{}",
+        crate::helpers::id::request_id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+#[tokio::test]
+async fn orders_from_a_stub_ca() {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let http_port = free_port().await;
+    let (ca, root) = StubCa::start(http_port).await;
+    let state_dir = temp_dir("state");
+
+    let config = config::Acme {
+        directory: format!("{}/dir", ca.base),
+        ca_root: Some(root),
+        accept_terms: true,
+        state_dir: state_dir.clone(),
+        challenge: AcmeChallenge::Http01,
+        http_port,
+        ..config::Acme::default()
+    };
+    let inner = Inner {
+        config,
+        account: OnceCell::new(),
+        challenges: Arc::new(Challenges::default()),
+    };
+    let responder = tokio::spawn(challenge::serve_http(http_port, inner.challenges.clone()));
+    // let the responder bind before the ca asks it
+    while TcpStream::connect(SocketAddr::from((Ipv4Addr::LOCALHOST, http_port)))
+        .await
+        .is_err()
+    {
+        tokio::task::yield_now().await;
+    }
+
+    let server: Server = serde_json::from_value(json!({
+        "host": "::1",
+        "port": 443,
+        "acme": ["b.test", "a.test"],
+    }))
+    .unwrap();
+
+    assert!(inner.renew("site", &server).await.unwrap());
+
+    let (cert_path, key_path) = inner.config.cert_paths("site");
+    let pem = std::fs::read(&cert_path).unwrap();
+    let chain: Vec<_> = CertificateDer::pem_slice_iter(&pem)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(chain.len(), 2);
+    assert!(x509::not_after(&chain[0]).is_some_and(|t| t > Utc::now()));
+    assert_eq!(
+        x509::subject_alt_names(&chain[0]),
+        vec!["DNS:a.test".to_string(), "DNS:b.test".to_string()]
+    );
+
+    // the key that was written is the one the certificate is for
+    let key = KeyPair::from_pem(&std::fs::read_to_string(&key_path).unwrap()).unwrap();
+    let (_, leaf) = x509_parser::parse_x509_certificate(&chain[0]).unwrap();
+    assert_eq!(
+        leaf.public_key().raw,
+        key.subject_public_key_info().as_slice()
+    );
+
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&key_path), 0o600);
+    assert_eq!(mode(&state_dir.join("account.json")), 0o600);
+    assert_eq!(
+        std::fs::read_to_string(cert_path.with_file_name("names")).unwrap(),
+        "a.test\nb.test"
+    );
+
+    // nothing half written is left around
+    let leftovers: Vec<_> = std::fs::read_dir(cert_path.parent().unwrap())
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+
+    // fresh for the same names, nothing is ordered again
+    assert!(!inner.renew("site", &server).await.unwrap());
+
+    responder.abort();
+    let _ = std::fs::remove_dir_all(&state_dir);
+    if let Some(dir) = inner.config.ca_root.as_deref().and_then(Path::parent) {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,2 +1,201 @@
-// todo
-mod error;
+mod policy;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderName};
+use http::{Request, StatusCode};
+use lru::LruCache;
+use tracing::debug;
+
+use crate::config::cache::{Cache as CacheConfig, RouteCache};
+
+/// bounded in-memory lru cache of whole responses, shared by every server
+pub struct Cache {
+    entries: Mutex<Entries>,
+    max_size: u64,
+    max_entries: usize,
+}
+
+pub struct Cached {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    stored: Instant,
+    expires: Instant,
+}
+
+impl Cached {
+    pub fn age(&self) -> Duration {
+        self.stored.elapsed()
+    }
+}
+
+// a response varying on request headers is stored as a `Vary` slot under the
+// base key, listing the headers, and one `Response` slot per variant
+enum Slot {
+    Vary(Vec<HeaderName>),
+    Response(Arc<Cached>),
+}
+
+struct Entries {
+    lru: LruCache<String, Slot>,
+    size: u64,
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                size: 0,
+            }),
+            max_size: config.max_size,
+            max_entries: config.max_entries,
+        }
+    }
+
+    pub fn get(&self, req: &Request<()>, policy: &RouteCache) -> Option<Arc<Cached>> {
+        if !policy::can_lookup(req, policy) {
+            return None;
+        }
+
+        let base = base_key(req);
+        let mut entries = self.lock();
+
+        let key = match entries.lru.get(&base)? {
+            Slot::Vary(names) => variant_key(&base, names, req),
+            Slot::Response(_) => base,
+        };
+
+        let cached = match entries.lru.get(&key)? {
+            Slot::Response(cached) => cached.clone(),
+            Slot::Vary(_) => return None,
+        };
+
+        if cached.expires <= Instant::now() {
+            entries.remove(&key);
+            debug!(key = %key, "cache_expired");
+            return None;
+        }
+
+        debug!(key = %key, "cache_hit");
+        Some(cached)
+    }
+
+    pub fn insert(
+        &self,
+        req: &Request<()>,
+        policy: &RouteCache,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    ) {
+        if !policy::can_store_request(req, policy) || body.len() as u64 > policy.max_entry_size {
+            return;
+        }
+
+        let Some(ttl) = policy::ttl(policy, status, &headers) else {
+            return;
+        };
+        let Some(vary) = policy::vary(&headers) else {
+            return;
+        };
+
+        let base = base_key(req);
+        let key = if vary.is_empty() {
+            base.clone()
+        } else {
+            variant_key(&base, &vary, req)
+        };
+
+        let now = Instant::now();
+        let cached = Arc::new(Cached {
+            status,
+            headers,
+            body,
+            stored: now,
+            expires: now + ttl,
+        });
+
+        if slot_size(&key, &Slot::Response(cached.clone())) > self.max_size {
+            return;
+        }
+
+        let mut entries = self.lock();
+        if !vary.is_empty() {
+            entries.put(base, Slot::Vary(vary));
+        }
+        entries.put(key.clone(), Slot::Response(cached));
+
+        while entries.size > self.max_size || entries.lru.len() > self.max_entries {
+            let Some((evicted, slot)) = entries.lru.pop_lru() else {
+                break;
+            };
+            entries.size -= slot_size(&evicted, &slot);
+            debug!(key = %evicted, "cache_evicted");
+        }
+
+        debug!(key = %key, ttl_secs = ttl.as_secs(), "cache_stored");
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Entries {
+    fn put(&mut self, key: String, slot: Slot) {
+        self.size += slot_size(&key, &slot);
+        if let Some(old) = self.lru.put(key.clone(), slot) {
+            self.size -= slot_size(&key, &old);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.lru.pop(key) {
+            self.size -= slot_size(key, &old);
+        }
+    }
+}
+
+fn base_key(req: &Request<()>) -> String {
+    let uri = req.uri();
+    format!(
+        "{} {}{}",
+        req.method(),
+        uri.authority().map_or("", |a| a.as_str()),
+        uri.path_and_query().map_or("/", |pq| pq.as_str())
+    )
+}
+
+fn variant_key(base: &str, vary: &[HeaderName], req: &Request<()>) -> String {
+    let mut key = base.to_string();
+    for name in vary {
+        key.push('\n');
+        key.push_str(name.as_str());
+        key.push(':');
+        for value in req.headers().get_all(name) {
+            key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+        }
+    }
+    key
+}
+
+// rough memory footprint, what `max_size` is measured in
+fn slot_size(key: &str, slot: &Slot) -> u64 {
+    let payload = match slot {
+        Slot::Vary(names) => names.iter().map(|n| n.as_str().len()).sum(),
+        Slot::Response(cached) => {
+            cached.body.len()
+                + cached
+                    .headers
+                    .iter()
+                    .map(|(n, v)| n.as_str().len() + v.len())
+                    .sum::<usize>()
+        }
+    };
+
+    (key.len() + payload) as u64
+}
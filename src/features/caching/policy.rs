@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use http::header::{self, HeaderMap, HeaderName};
+use http::{Method, Request, StatusCode};
+
+use crate::config::cache::RouteCache;
+
+// statuses a shared cache may keep without being told explicitly (rfc 9110 15.1)
+const CACHEABLE_STATUS: [u16; 6] = [200, 203, 204, 301, 404, 410];
+
+/// whether a stored response may be used for this request
+pub(super) fn can_lookup(req: &Request<()>, policy: &RouteCache) -> bool {
+    if !can_store_request(req, policy) {
+        return false;
+    }
+
+    // only whole responses are stored, range requests go to the origin
+    if req.headers().contains_key(header::RANGE) {
+        return false;
+    }
+
+    // no-cache asks for a fresh answer; storing that answer is still fine
+    !policy.respect_cache_control || !has_directive(req.headers(), "no-cache")
+}
+
+/// whether the response to this request may be stored at all
+pub(super) fn can_store_request(req: &Request<()>, policy: &RouteCache) -> bool {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return false;
+    }
+
+    // a shared cache must not hand out answers to authenticated requests
+    if req.headers().contains_key(header::AUTHORIZATION) {
+        return false;
+    }
+
+    !policy.respect_cache_control || !has_directive(req.headers(), "no-store")
+}
+
+/// how long a response may be kept, `None` when it must not be stored
+pub(super) fn ttl(
+    policy: &RouteCache,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Option<Duration> {
+    if !CACHEABLE_STATUS.contains(&status.as_u16()) || headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+
+    let default = Duration::from_secs(policy.ttl_secs);
+    if !policy.respect_cache_control {
+        return Some(default).filter(|ttl| !ttl.is_zero());
+    }
+
+    let directives = directives(headers);
+    let find = |name: &str| directives.iter().find(|(n, _)| n == name);
+
+    if ["no-store", "private", "no-cache"]
+        .iter()
+        .any(|name| find(name).is_some())
+    {
+        return None;
+    }
+
+    // s-maxage is meant for shared caches like this one and wins over max-age
+    let ttl = ["s-maxage", "max-age"]
+        .iter()
+        .find_map(|name| find(name)?.1.as_deref()?.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(default);
+
+    Some(ttl).filter(|ttl| !ttl.is_zero())
+}
+
+/// request headers the response varies on; `None` for `vary: *`, which can't be cached
+pub(super) fn vary(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+
+    for value in headers.get_all(header::VARY) {
+        for name in value.to_str().ok()?.split(',') {
+            let name = name.trim();
+            if name == "*" {
+                return None;
+            }
+            if let Ok(name) = HeaderName::try_from(name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    names.dedup();
+    Some(names)
+}
+
+fn has_directive(headers: &HeaderMap, name: &str) -> bool {
+    directives(headers).iter().any(|(n, _)| n == name)
+}
+
+// cache-control as lowercased (name, value) pairs
+fn directives(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| match d.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_ascii_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (d.trim().to_ascii_lowercase(), None),
+        })
+        .collect()
+}
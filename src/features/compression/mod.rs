@@ -0,0 +1,32 @@
+use std::pin::Pin;
+
+use async_compression::Level;
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+use tokio::io::{AsyncRead, BufReader};
+
+use crate::config::Compression;
+use crate::http::encoding::Encoding;
+
+pub type Encoded<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+/// `body`, compressed with `encoding` at the configured level while it is read
+pub fn encode<'a, R>(encoding: Encoding, body: R, config: &Compression) -> Encoded<'a>
+where
+    R: AsyncRead + Send + 'a,
+{
+    let body = BufReader::new(body);
+    match encoding {
+        Encoding::Brotli => Box::pin(BrotliEncoder::with_quality(
+            body,
+            Level::Precise(config.brotli_level),
+        )),
+        Encoding::Zstd => Box::pin(ZstdEncoder::with_quality(
+            body,
+            Level::Precise(config.zstd_level),
+        )),
+        Encoding::Gzip => Box::pin(GzipEncoder::with_quality(
+            body,
+            Level::Precise(config.gzip_level),
+        )),
+    }
+}
@@ -9,3 +9,12 @@ pub mod health;
 
 #[cfg(feature = "scripting")]
 pub mod scripting;
+
+#[cfg(feature = "compression")]
+pub mod compression;
+
+#[cfg(feature = "acme")]
+pub mod acme;
+
+#[cfg(feature = "ratelimit")]
+pub mod ratelimit;
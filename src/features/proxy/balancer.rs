@@ -0,0 +1,633 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::{Request, Uri};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::error::ProxyError;
+use super::health;
+use crate::config::upstream::{Balance, HashKey, Upstream, UpstreamHealth};
+
+// virtual nodes per unit of weight on the consistent hash ring
+const RING_POINTS_PER_WEIGHT: u32 = 64;
+
+// keeps the ring, and the round robin counters, small
+const MAX_WEIGHT: u32 = 1000;
+
+/// runtime view of every configured upstream group
+pub struct Upstreams {
+    pools: HashMap<String, Arc<Pool>>,
+    probes: Vec<JoinHandle<()>>,
+}
+
+impl Upstreams {
+    pub fn new(config: &HashMap<String, Upstream>) -> Result<Self, ProxyError> {
+        let pools: HashMap<String, Arc<Pool>> = config
+            .iter()
+            .map(|(name, upstream)| Ok((name.clone(), Arc::new(Pool::new(name, upstream)?))))
+            .collect::<Result<_, ProxyError>>()?;
+
+        let probes = pools
+            .values()
+            .filter_map(|p| health::spawn(p.clone()))
+            .collect();
+
+        Ok(Self { pools, probes })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pool> {
+        self.pools.get(name).map(|p| &**p)
+    }
+
+    /// that a proxy `upstream` names a group here or is an http:// uri,
+    /// for checking routes when the config is loaded
+    pub fn check(&self, upstream: &str) -> Result<(), ProxyError> {
+        if self.pools.contains_key(upstream) {
+            return Ok(());
+        }
+        super::upstream_uri(upstream, "/").map_err(|_| ProxyError::InvalidUpstream {
+            upstream: upstream.to_string(),
+            reason: "neither an upstreams group nor an http://host:port uri".into(),
+        })?;
+        Ok(())
+    }
+}
+
+// probes belong to one config generation; stop them when it is replaced
+impl Drop for Upstreams {
+    fn drop(&mut self) {
+        for probe in &self.probes {
+            probe.abort();
+        }
+    }
+}
+
+pub struct Pool {
+    name: String,
+    balance: Balance,
+    hash_key: HashKey,
+    pub(super) health: UpstreamHealth,
+    // parsed `health.path`
+    pub(super) probe_path: Option<Uri>,
+    pub(super) backends: Vec<Backend>,
+    // smooth weighted round robin state, one counter per backend
+    current: Mutex<Vec<i64>>,
+    // sorted (point, backend index) pairs
+    ring: Vec<(u64, usize)>,
+}
+
+pub struct Backend {
+    pub(super) address: String,
+    weight: u32,
+    active: AtomicUsize,
+    state: Mutex<HealthState>,
+}
+
+struct HealthState {
+    up: bool,
+    fails: u32,
+    successes: u32,
+    down_since: Option<Instant>,
+}
+
+/// keeps a backend's active request count up while a request is in flight
+pub struct BackendGuard<'a> {
+    pool: &'a Pool,
+    index: usize,
+}
+
+impl BackendGuard<'_> {
+    pub fn address(&self) -> &str {
+        &self.pool.backends[self.index].address
+    }
+
+    /// passive health: feed the outcome of the proxied request back
+    pub fn record(&self, ok: bool) {
+        self.pool.record(self.index, ok, "proxy");
+    }
+}
+
+impl Drop for BackendGuard<'_> {
+    fn drop(&mut self) {
+        self.pool.backends[self.index]
+            .active
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Pool {
+    fn new(name: &str, config: &Upstream) -> Result<Self, ProxyError> {
+        let invalid = |reason: String| ProxyError::InvalidUpstream {
+            upstream: name.to_string(),
+            reason,
+        };
+
+        if let Some(backend) = config.backends.iter().find(|b| b.weight > MAX_WEIGHT) {
+            return Err(invalid(format!(
+                "weight {} of backend '{}' is above {MAX_WEIGHT}",
+                backend.weight, backend.address
+            )));
+        }
+
+        for backend in &config.backends {
+            super::upstream_uri(&backend.address, "/")?;
+        }
+
+        let probe_path = config
+            .health
+            .path
+            .as_deref()
+            .map(|path| {
+                path.parse::<Uri>()
+                    .ok()
+                    .filter(|uri| uri.scheme().is_none() && uri.path().starts_with('/'))
+                    .ok_or_else(|| invalid(format!("invalid health check path '{path}'")))
+            })
+            .transpose()?;
+
+        let backends: Vec<Backend> = config
+            .backends
+            .iter()
+            .map(|b| Backend {
+                address: b.address.clone(),
+                weight: b.weight,
+                active: AtomicUsize::new(0),
+                state: Mutex::new(HealthState {
+                    up: true,
+                    fails: 0,
+                    successes: 0,
+                    down_since: None,
+                }),
+            })
+            .collect();
+
+        let mut ring = Vec::new();
+        if config.balance == Balance::ConsistentHash {
+            for (index, backend) in backends.iter().enumerate() {
+                let points = backend
+                    .weight
+                    .checked_mul(RING_POINTS_PER_WEIGHT)
+                    .ok_or_else(|| invalid(format!("weight {} too large", backend.weight)))?;
+                for point in 0..points {
+                    ring.push((
+                        stable_hash(format!("{}#{point}", backend.address).as_bytes()),
+                        index,
+                    ));
+                }
+            }
+            ring.sort_unstable();
+        }
+
+        info!(
+            upstream = name,
+            backends = backends.len(),
+            balance = ?config.balance,
+            "upstream_configured"
+        );
+
+        Ok(Self {
+            name: name.to_string(),
+            balance: config.balance,
+            hash_key: config.hash_key.clone(),
+            health: config.health.clone(),
+            probe_path,
+            current: Mutex::new(vec![0; backends.len()]),
+            backends,
+            ring,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// pick a backend for this request, `None` when nothing can take it
+    pub fn select(&self, req: &Request<()>, remote: SocketAddr) -> Option<BackendGuard<'_>> {
+        let index = match self.balance {
+            Balance::RoundRobin => self.round_robin(),
+            Balance::LeastConnections => self.least_connections(),
+            Balance::RandomTwoChoices => self.random_two_choices(),
+            Balance::ConsistentHash => self.consistent_hash(req, remote),
+        }?;
+
+        self.backends[index].active.fetch_add(1, Ordering::Relaxed);
+        Some(BackendGuard { pool: self, index })
+    }
+
+    /// count a success or failure against a backend and move it in or out of rotation
+    pub(super) fn record(&self, index: usize, ok: bool, source: &'static str) {
+        let backend = &self.backends[index];
+        let mut state = backend.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if ok {
+            state.fails = 0;
+            if !state.up {
+                state.successes += 1;
+                if state.successes >= self.health.recover_after {
+                    state.up = true;
+                    state.successes = 0;
+                    state.down_since = None;
+                    info!(
+                        upstream = %self.name,
+                        backend = %backend.address,
+                        source,
+                        "upstream_backend_up"
+                    );
+                }
+            }
+            return;
+        }
+
+        state.successes = 0;
+        state.fails += 1;
+
+        if state.up && state.fails >= self.health.max_fails {
+            state.up = false;
+            state.down_since = Some(Instant::now());
+            warn!(
+                upstream = %self.name,
+                backend = %backend.address,
+                fails = state.fails,
+                source,
+                "upstream_backend_down"
+            );
+        } else if !state.up {
+            // a failed retry starts the wait over
+            state.down_since = Some(Instant::now());
+        }
+    }
+
+    fn is_eligible(&self, index: usize) -> bool {
+        let backend = &self.backends[index];
+        if backend.weight == 0 {
+            return false;
+        }
+
+        let state = backend.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.up {
+            return true;
+        }
+
+        // with probing on only probes bring a backend back; otherwise live
+        // traffic is let through again once the fail timeout is over
+        self.probe_path.is_none()
+            && state.down_since.is_none_or(|since| {
+                since.elapsed() >= Duration::from_secs(self.health.fail_timeout_secs)
+            })
+    }
+
+    fn eligible(&self) -> impl Iterator<Item = (usize, &Backend)> {
+        self.backends
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_eligible(*i))
+    }
+
+    // nginx's smooth weighted round robin: even spread, no bursts on heavy backends
+    fn round_robin(&self) -> Option<usize> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut total = 0i64;
+        let mut best: Option<usize> = None;
+        for (i, backend) in self.eligible() {
+            current[i] += i64::from(backend.weight);
+            total += i64::from(backend.weight);
+            if best.is_none_or(|b| current[i] > current[b]) {
+                best = Some(i);
+            }
+        }
+
+        let best = best?;
+        current[best] -= total;
+        Some(best)
+    }
+
+    fn least_connections(&self) -> Option<usize> {
+        self.eligible()
+            .min_by(|(_, a), (_, b)| load_cmp(a, b))
+            .map(|(i, _)| i)
+    }
+
+    // power of two choices: two weighted random picks, keep the less loaded one
+    fn random_two_choices(&self) -> Option<usize> {
+        let first = self.weighted_random()?;
+        let second = self.weighted_random()?;
+
+        let (a, b) = (&self.backends[first], &self.backends[second]);
+        Some(if load_cmp(b, a).is_lt() {
+            second
+        } else {
+            first
+        })
+    }
+
+    fn weighted_random(&self) -> Option<usize> {
+        let total: u64 = self.eligible().map(|(_, b)| u64::from(b.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = random() % total;
+        for (i, backend) in self.eligible() {
+            let weight = u64::from(backend.weight);
+            if pick < weight {
+                return Some(i);
+            }
+            pick -= weight;
+        }
+        None
+    }
+
+    fn consistent_hash(&self, req: &Request<()>, remote: SocketAddr) -> Option<usize> {
+        if self.ring.is_empty() {
+            return None;
+        }
+
+        let client_ip = || remote.ip().to_canonical().to_string().into_bytes();
+        let key = match &self.hash_key {
+            HashKey::ClientIp => client_ip(),
+            // requests without the header are still spread, by client ip
+            HashKey::Header(name) => req
+                .headers()
+                .get(name.as_str())
+                .map(|v| v.as_bytes().to_vec())
+                .unwrap_or_else(client_ip),
+        };
+
+        let hash = stable_hash(&key);
+        let start = self.ring.partition_point(|(point, _)| *point < hash);
+
+        // walk clockwise until a backend that can take the request shows up
+        (0..self.ring.len())
+            .map(|offset| self.ring[(start + offset) % self.ring.len()].1)
+            .find(|&i| self.is_eligible(i))
+    }
+}
+
+// compares active / weight without dividing
+fn load_cmp(a: &Backend, b: &Backend) -> std::cmp::Ordering {
+    let a_load = a.active.load(Ordering::Relaxed) as u64 * u64::from(b.weight);
+    let b_load = b.active.load(Ordering::Relaxed) as u64 * u64::from(a.weight);
+    a_load.cmp(&b_load)
+}
+
+// stable across restarts, so hashed clients keep landing on the same backend.
+// fnv alone leaves keys that differ in their last byte, like neighbouring client
+// addresses, next to each other on the ring; murmur3's finalizer spreads them
+fn stable_hash(data: &[u8]) -> u64 {
+    let mut hash = data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+// xorshift64*, seeded per thread; good enough for picking backends
+fn random() -> u64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(0u64) | 1);
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use http_body_util::channel::Channel;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::config::upstream::Backend as BackendConfig;
+    use crate::features::proxy::{client, upstream_uri};
+
+    // answers every request with an empty 200 and counts it
+    async fn stand_in() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let served = Arc::new(AtomicUsize::new(0));
+
+        let count = served.clone();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let count = count.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match conn.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => head.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    count.fetch_add(1, Ordering::Relaxed);
+                    let _ = conn
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                        )
+                        .await;
+                });
+            }
+        });
+
+        (address, served)
+    }
+
+    fn pool(balance: Balance, backends: &[(&str, u32)]) -> Pool {
+        let config = Upstream {
+            backends: backends
+                .iter()
+                .map(|(address, weight)| BackendConfig {
+                    address: address.to_string(),
+                    weight: *weight,
+                })
+                .collect(),
+            balance,
+            hash_key: HashKey::ClientIp,
+            health: UpstreamHealth::default(),
+        };
+        Pool::new("test", &config).unwrap()
+    }
+
+    // one proxied request, the way `forward` does it
+    async fn send(pool: &Pool, remote: SocketAddr) -> Option<String> {
+        let req = Request::get("/").body(()).unwrap();
+        let backend = pool.select(&req, remote)?;
+        let address = backend.address().to_string();
+
+        let (_, body) = Channel::new(1);
+        let upstream = Request::get(upstream_uri(&address, "/").unwrap())
+            .body(body)
+            .unwrap();
+        let ok = client()
+            .request(upstream)
+            .await
+            .is_ok_and(|r| r.status().is_success());
+        backend.record(ok);
+
+        ok.then_some(address)
+    }
+
+    fn remote(n: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, n], 4433))
+    }
+
+    #[tokio::test]
+    async fn round_robin_spreads_by_weight() {
+        let (a, served_a) = stand_in().await;
+        let (b, served_b) = stand_in().await;
+        let (c, served_c) = stand_in().await;
+        let (off, served_off) = stand_in().await;
+        let pool = pool(Balance::RoundRobin, &[(&a, 1), (&b, 2), (&c, 3), (&off, 0)]);
+
+        for _ in 0..60 {
+            assert!(send(&pool, remote(1)).await.is_some());
+        }
+
+        let served =
+            [&served_a, &served_b, &served_c, &served_off].map(|s| s.load(Ordering::Relaxed));
+        assert_eq!(served, [10, 20, 30, 0]);
+    }
+
+    #[tokio::test]
+    async fn failing_backend_leaves_rotation() {
+        let (a, served_a) = stand_in().await;
+        let (b, served_b) = stand_in().await;
+        // nothing listens here anymore
+        let gone = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let pool = pool(Balance::RoundRobin, &[(&a, 1), (&b, 1), (&gone, 1)]);
+
+        let mut failed = 0;
+        for _ in 0..30 {
+            if send(&pool, remote(1)).await.is_none() {
+                failed += 1;
+            }
+        }
+
+        // max_fails requests, then it's skipped
+        assert_eq!(failed, UpstreamHealth::default_max_fails());
+        assert_eq!(
+            served_a.load(Ordering::Relaxed) + served_b.load(Ordering::Relaxed),
+            30 - failed as usize
+        );
+        assert!(
+            served_a
+                .load(Ordering::Relaxed)
+                .abs_diff(served_b.load(Ordering::Relaxed))
+                <= 1
+        );
+    }
+
+    #[tokio::test]
+    async fn consistent_hash_keeps_clients_on_one_backend() {
+        let mut backends = Vec::new();
+        for _ in 0..3 {
+            backends.push(stand_in().await);
+        }
+        let addresses: Vec<(&str, u32)> = backends.iter().map(|(a, _)| (a.as_str(), 1)).collect();
+        let pool = pool(Balance::ConsistentHash, &addresses);
+
+        let mut used = std::collections::HashSet::new();
+        for n in 1..=32 {
+            let first = send(&pool, remote(n)).await.unwrap();
+            for _ in 0..3 {
+                assert_eq!(send(&pool, remote(n)).await.unwrap(), first);
+            }
+            used.insert(first);
+        }
+
+        // 32 clients over 3 backends: all of them get some
+        assert_eq!(used.len(), 3);
+        let total: usize = backends
+            .iter()
+            .map(|(_, s)| s.load(Ordering::Relaxed))
+            .sum();
+        assert_eq!(total, 32 * 4);
+    }
+
+    #[test]
+    fn weight_is_bounded() {
+        let config = Upstream {
+            backends: vec![BackendConfig {
+                address: "http://127.0.0.1:1".to_string(),
+                weight: u32::MAX,
+            }],
+            balance: Balance::ConsistentHash,
+            hash_key: HashKey::ClientIp,
+            health: UpstreamHealth::default(),
+        };
+        assert!(matches!(
+            Pool::new("test", &config),
+            Err(ProxyError::InvalidUpstream { .. })
+        ));
+    }
+
+    #[test]
+    fn upstreams_are_checked() {
+        let config = |address: &str| Upstream {
+            backends: vec![BackendConfig {
+                address: address.to_string(),
+                weight: 1,
+            }],
+            balance: Balance::RoundRobin,
+            hash_key: HashKey::ClientIp,
+            health: UpstreamHealth::default(),
+        };
+
+        let upstreams = Upstreams::new(&HashMap::from([(
+            "api".to_string(),
+            config("http://[::1]:8080"),
+        )]))
+        .unwrap();
+        assert!(upstreams.check("api").is_ok());
+        assert!(upstreams.check("http://127.0.0.1:8080/v1").is_ok());
+        for upstream in ["apj", "https://127.0.0.1", "127.0.0.1:8080", "http:///x"] {
+            assert!(upstreams.check(upstream).is_err(), "{upstream}");
+        }
+
+        assert!(
+            Upstreams::new(&HashMap::from([("api".to_string(), config("[::1]:8080"))])).is_err()
+        );
+    }
+
+    #[test]
+    fn health_path_is_checked() {
+        for (path, valid) in [
+            ("/health?full=1", true),
+            ("health check", false),
+            ("http://127.0.0.1/health", false),
+        ] {
+            let config = Upstream {
+                backends: Vec::new(),
+                balance: Balance::RoundRobin,
+                hash_key: HashKey::ClientIp,
+                health: UpstreamHealth {
+                    path: Some(path.to_string()),
+                    ..UpstreamHealth::default()
+                },
+            };
+            assert_eq!(Pool::new("test", &config).is_ok(), valid, "{path}");
+        }
+    }
+}
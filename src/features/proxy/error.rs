@@ -1 +1,31 @@
-// todo
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("invalid upstream '{upstream}': {reason}")]
+    InvalidUpstream { upstream: String, reason: String },
+
+    #[error("no backend available in upstream '{0}'")]
+    NoBackend(String),
+
+    #[error("upstream request failed: {0}")]
+    Upstream(#[from] hyper_util::client::legacy::Error),
+
+    #[error("upstream did not respond within {0:?}")]
+    Timeout(std::time::Duration),
+
+    #[error("upstream body error: {0}")]
+    UpstreamBody(#[from] hyper::Error),
+
+    #[error("failed to build upstream request: {0}")]
+    Request(#[from] http::Error),
+
+    #[error("request body aborted by client")]
+    BodyAborted,
+
+    #[error(transparent)]
+    Body(#[from] crate::http::body::error::BodyError),
+
+    #[error(transparent)]
+    Stream(#[from] h3::error::StreamError),
+}
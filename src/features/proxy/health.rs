@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::header::{self, HeaderValue};
+use http::{Request, Uri};
+use http_body_util::channel::Channel;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::debug;
+
+use super::balancer::Pool;
+use super::{client, upstream_uri};
+use crate::APP_NAME;
+
+/// start probing every backend of `pool`, if the pool has a health check path
+pub(super) fn spawn(pool: Arc<Pool>) -> Option<JoinHandle<()>> {
+    let path = pool.probe_path.clone()?;
+    Some(tokio::spawn(run(pool, path)))
+}
+
+async fn run(pool: Arc<Pool>, path: Uri) {
+    let mut interval = time::interval(Duration::from_secs(pool.health.interval_secs.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let mut probes = JoinSet::new();
+        for index in 0..pool.backends.len() {
+            let pool = pool.clone();
+            let path = path.clone();
+
+            probes.spawn(async move {
+                let backend = &pool.backends[index].address;
+                let result = probe(backend, &path, &pool).await;
+
+                if let Err(reason) = &result {
+                    debug!(
+                        upstream = %pool.name(),
+                        backend = %backend,
+                        reason = %reason,
+                        "upstream_probe_failed"
+                    );
+                }
+                pool.record(index, result.is_ok(), "probe");
+            });
+        }
+
+        while probes.join_next().await.is_some() {}
+    }
+}
+
+async fn probe(backend: &str, path: &Uri, pool: &Pool) -> Result<(), String> {
+    let path_and_query = path.path_and_query().map_or("/", |pq| pq.as_str());
+    let uri = upstream_uri(backend, path_and_query).map_err(|e| e.to_string())?;
+
+    // sender dropped right away: an empty body
+    let (_, body) = Channel::new(1);
+    let req = Request::get(uri)
+        .header(header::USER_AGENT, HeaderValue::from_static(APP_NAME))
+        .body(body)
+        .map_err(|e| e.to_string())?;
+
+    let timeout = Duration::from_secs(pool.health.timeout_secs);
+    let response = time::timeout(timeout, client().request(req))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    if response.status().as_u16() != pool.health.expected_status {
+        return Err(format!("unexpected status {}", response.status()));
+    }
+
+    Ok(())
+}
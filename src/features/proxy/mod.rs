@@ -1,2 +1,360 @@
+mod balancer;
 mod error;
-// to write!
+mod health;
+
+pub use balancer::Upstreams;
+pub use error::ProxyError;
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use bytes::Bytes;
+use h3::server::RequestStream;
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::uri::Scheme;
+use http::{Request, Response, Uri};
+use http_body_util::BodyExt;
+use http_body_util::channel::{Channel, Sender};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use tracing::debug;
+
+use crate::http::body::Body;
+use crate::http::headers::HeaderRewrite;
+use crate::http::response::{Capture, Head, send_head};
+
+type UpstreamBody = Channel<Bytes, ProxyError>;
+
+// frames buffered between the h3 request stream and the upstream connection
+const BODY_CHANNEL_FRAMES: usize = 16;
+
+// tcp connect to a backend; the response itself has the action's `timeout_secs`
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const EARLY_DATA: HeaderName = HeaderName::from_static("early-data");
+
+// connection-specific headers: never forwarded, and forbidden in http3 anyway
+const HOP_BY_HOP: [&str; 7] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn client() -> &'static Client<HttpConnector, UpstreamBody> {
+    static CLIENT: OnceLock<Client<HttpConnector, UpstreamBody>> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(CONNECT_TIMEOUT));
+        Client::builder(TokioExecutor::new()).build(connector)
+    })
+}
+
+/// forward `req` to `upstream` and stream the upstream response back on `stream`.
+/// `upstream` names a group from `upstreams`, otherwise it is used as a plain
+/// `http://host:port[/prefix]` uri. nothing is sent to the client if the
+/// upstream can't be reached, so callers can still answer with their own error.
+/// `path` replaces the request path upstream, the query string is kept.
+/// requests that came as 0-rtt early data are marked so (`early_data`).
+/// an upstream that hasn't started its response within `timeout` fails with
+/// `ProxyError::Timeout`.
+/// the request body is read through `body`, which enforces its limits, and
+/// `rewrite` has the last word on the headers sent upstream.
+/// the response head goes out through `head`, and is also copied into
+/// `capture` with the body, when given.
+#[allow(clippy::too_many_arguments)]
+pub async fn forward(
+    req: &Request<()>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    body: &mut Body,
+    upstream: &str,
+    path: Option<&str>,
+    upstreams: &Upstreams,
+    remote: SocketAddr,
+    early_data: bool,
+    timeout: Duration,
+    rewrite: &HeaderRewrite,
+    mut capture: Option<&mut Capture>,
+) -> Result<(), ProxyError> {
+    let backend = match upstreams.get(upstream) {
+        Some(pool) => Some(
+            pool.select(req, remote)
+                .ok_or_else(|| ProxyError::NoBackend(pool.name().to_string()))?,
+        ),
+        None => None,
+    };
+    let address = backend.as_ref().map_or(upstream, |b| b.address());
+
+    let path_and_query = match (path, req.uri().query()) {
+        (Some(path), Some(query)) => format!("{path}?{query}"),
+        (Some(path), None) => path.to_string(),
+        (None, _) => req
+            .uri()
+            .path_and_query()
+            .map_or("/", |pq| pq.as_str())
+            .to_string(),
+    };
+
+    let uri = upstream_uri(address, &path_and_query)?;
+    let (body_tx, upstream_body) = Channel::new(BODY_CHANNEL_FRAMES);
+
+    let mut builder = Request::builder().method(req.method().clone()).uri(uri);
+    if let Some(headers) = builder.headers_mut() {
+        copy_headers(req.headers(), headers);
+        add_forwarded(req, remote, headers);
+        // lets the upstream answer 425 itself (rfc 8470 5.1)
+        if early_data {
+            headers.insert(EARLY_DATA, HeaderValue::from_static("1"));
+        }
+        rewrite.apply(headers);
+    }
+    let upstream_req = builder.body(upstream_body)?;
+
+    debug!(
+        method = %req.method(),
+        upstream = %upstream_req.uri(),
+        remote = %remote,
+        "proxy_forward"
+    );
+
+    // the request body is pumped while the upstream works on the request, and
+    // after that: an upstream may answer before it has read all of it
+    let mut upload = Upload::new(body, body_tx);
+    let response = tokio::time::timeout(timeout, client().request(upstream_req));
+    tokio::pin!(response);
+    let response = loop {
+        tokio::select! {
+            response = &mut response => break response,
+            () = upload.step(stream), if !upload.done() => {}
+        }
+    };
+    let response = match response {
+        Ok(response) => response.map_err(ProxyError::from),
+        Err(_) => Err(ProxyError::Timeout(timeout)),
+    };
+
+    // a backend answering with server errors is as unhealthy as one not answering
+    if let Some(backend) = &backend {
+        backend.record(
+            response
+                .as_ref()
+                .is_ok_and(|r| !r.status().is_server_error()),
+        );
+    }
+
+    // an upstream that failed because the client's body did is the client's fault
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return Err(upload.error.take().unwrap_or(e)),
+    };
+    let (parts, mut body) = response.into_parts();
+
+    let mut builder = Response::builder().status(parts.status);
+    if let Some(headers) = builder.headers_mut() {
+        copy_headers(&parts.headers, headers);
+    }
+    let response = builder.body(())?;
+    if let Some(capture) = capture.as_mut() {
+        capture.head(response.status(), response.headers());
+    }
+    send_head(stream, head, response).await?;
+
+    let mut trailers = None;
+    loop {
+        let frame = tokio::select! {
+            frame = body.frame() => frame,
+            () = upload.step(stream), if !upload.done() => continue,
+        };
+        let Some(frame) = frame else {
+            break;
+        };
+        match frame?.into_data() {
+            Ok(data) => {
+                if let Some(capture) = capture.as_mut() {
+                    capture.data(&data);
+                }
+                stream.send_data(data).await?
+            }
+            Err(frame) => {
+                if let Ok(t) = frame.into_trailers() {
+                    trailers = Some(t);
+                }
+            }
+        }
+    }
+
+    if let Some(trailers) = trailers {
+        stream.send_trailers(trailers).await?;
+    }
+    stream.finish().await?;
+
+    if let Some(e) = upload.error {
+        debug!(error = %e, "proxy_request_body_failed");
+    }
+    debug!(status = parts.status.as_u16(), "proxy_complete");
+    Ok(())
+}
+
+// the request body on its way upstream, a step at a time so it can go on
+// while the response comes back. a step may be cancelled and taken again
+struct Upload<'b> {
+    body: &'b mut Body,
+    // None once the whole body went out, or nobody takes more of it
+    tx: Option<Sender<Bytes, ProxyError>>,
+    // read from the client, not yet taken by the upstream
+    pending: Option<Pending>,
+    error: Option<ProxyError>,
+}
+
+enum Pending {
+    Data(Bytes),
+    Trailers(HeaderMap),
+}
+
+impl<'b> Upload<'b> {
+    fn new(body: &'b mut Body, tx: Sender<Bytes, ProxyError>) -> Self {
+        Self {
+            body,
+            tx: Some(tx),
+            pending: None,
+            error: None,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.tx.is_none()
+    }
+
+    async fn step(&mut self, stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>) {
+        let Some(tx) = &mut self.tx else {
+            return;
+        };
+
+        if let Some(pending) = &self.pending {
+            let sent = match pending {
+                Pending::Data(data) => tx.send_data(data.clone()).await,
+                Pending::Trailers(trailers) => tx.send_trailers(trailers.clone()).await,
+            };
+            let last = matches!(pending, Pending::Trailers(_));
+            self.pending = None;
+            // after trailers, or when the upstream stopped reading the body
+            if last || sent.is_err() {
+                self.tx = None;
+            }
+            return;
+        }
+
+        let read = match self.body.data(stream).await {
+            Ok(Some(data)) => Ok(Some(Pending::Data(data))),
+            Ok(None) => self
+                .body
+                .trailers(stream)
+                .await
+                .map(|trailers| trailers.map(Pending::Trailers)),
+            Err(e) => Err(e),
+        };
+
+        match read {
+            Ok(Some(pending)) => self.pending = Some(pending),
+            Ok(None) => self.tx = None,
+            // make sure the upstream never sees a truncated body as a complete one
+            Err(e) => {
+                if let Some(tx) = self.tx.take() {
+                    tx.abort(ProxyError::BodyAborted);
+                }
+                self.error = Some(e.into());
+            }
+        }
+    }
+}
+
+fn upstream_uri(upstream: &str, path_and_query: &str) -> Result<Uri, ProxyError> {
+    let invalid = |reason: String| ProxyError::InvalidUpstream {
+        upstream: upstream.to_string(),
+        reason,
+    };
+
+    let base: Uri = upstream.parse().map_err(|e| invalid(format!("{e}")))?;
+
+    if base.scheme() != Some(&Scheme::HTTP) {
+        return Err(invalid("only http:// upstreams are supported".into()));
+    }
+
+    let authority = base
+        .authority()
+        .cloned()
+        .ok_or_else(|| invalid("missing host".into()))?;
+
+    let prefix = base.path().trim_end_matches('/');
+
+    Ok(Uri::builder()
+        .scheme(Scheme::HTTP)
+        .authority(authority)
+        .path_and_query(format!("{prefix}{path_and_query}"))
+        .build()?)
+}
+
+fn copy_headers(from: &HeaderMap, to: &mut HeaderMap) {
+    // headers named in `connection` are hop-by-hop as well
+    let listed: Vec<String> = from
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+
+    for (name, value) in from {
+        let name_str = name.as_str();
+        if HOP_BY_HOP.contains(&name_str) || listed.iter().any(|l| l == name_str) {
+            continue;
+        }
+        to.append(name.clone(), value.clone());
+    }
+}
+
+fn add_forwarded(req: &Request<()>, remote: SocketAddr, headers: &mut HeaderMap) {
+    let ip = remote.ip().to_canonical();
+
+    // keep the original authority; h3 carries it in the uri instead of a host header
+    if let Some(authority) = req.uri().authority()
+        && let Ok(value) = HeaderValue::from_str(authority.as_str())
+    {
+        headers.insert(header::HOST, value);
+    }
+
+    append_list(headers, X_FORWARDED_FOR, &ip.to_string());
+
+    // rfc 7239: ipv6 nodes are bracketed and the whole node quoted
+    let node = match ip {
+        IpAddr::V6(v6) => format!("\"[{v6}]:{}\"", remote.port()),
+        IpAddr::V4(v4) => format!("\"{v4}:{}\"", remote.port()),
+    };
+    let mut forwarded = format!("for={node};proto=https");
+    if let Some(authority) = req.uri().authority() {
+        forwarded.push_str(&format!(";host=\"{authority}\""));
+    }
+    append_list(headers, header::FORWARDED, &forwarded);
+
+    headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static("https"));
+}
+
+fn append_list(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let mut values: Vec<&str> = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    values.push(value);
+
+    if let Ok(combined) = HeaderValue::from_str(&values.join(", ")) {
+        headers.insert(name, combined);
+    }
+}
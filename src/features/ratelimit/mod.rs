@@ -0,0 +1,204 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use tracing::debug;
+
+use crate::config::{RateLimit, RateLimits};
+
+// buckets looked at for one that has refilled, least recently seen first
+const EVICT_SCAN: usize = 32;
+
+/// token buckets of every rate limit, shared by every server. once `max_keys`
+/// are tracked, a new client takes the place of a bucket that has refilled
+/// completely, which is no different from a new one; while none has, new
+/// clients are refused.
+pub struct RateLimiter {
+    max_keys: usize,
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    capacity: f64,
+    // tokens per second
+    rate: f64,
+}
+
+/// how a request fared against one limit
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome {
+    pub allowed: bool,
+    /// requests per period, as configured
+    pub limit: u32,
+    pub period_secs: u64,
+    /// requests left right now
+    pub remaining: u32,
+    /// until the bucket is full again
+    pub reset: Duration,
+    /// until the next request is allowed; zero when this one was
+    pub retry_after: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimits) -> Self {
+        Self {
+            max_keys: config.max_keys.max(1),
+            buckets: Mutex::new(LruCache::unbounded()),
+        }
+    }
+
+    /// take one request from the bucket of `key` under `limit`; `scope` keeps
+    /// the buckets of different limits apart
+    pub fn check(&self, scope: &str, key: &str, limit: &RateLimit) -> Outcome {
+        let period = Duration::from_secs(limit.period_secs.max(1));
+        if limit.requests == 0 {
+            return Outcome {
+                allowed: false,
+                limit: 0,
+                period_secs: period.as_secs(),
+                remaining: 0,
+                reset: period,
+                retry_after: period,
+            };
+        }
+
+        let capacity = f64::from(limit.burst.unwrap_or(limit.requests).max(1));
+        let rate = f64::from(limit.requests) / period.as_secs_f64();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let id = format!("{scope}\n{key}");
+        if !buckets.contains(&id)
+            && buckets.len() >= self.max_keys
+            && let Err(wait) = evict(&mut buckets, now)
+        {
+            drop(buckets);
+            debug!(scope = %scope.replace('\n', "/"), key = %key, retry_after = wait.as_secs(), "rate_limit_keys_full");
+            return Outcome {
+                allowed: false,
+                limit: limit.requests,
+                period_secs: period.as_secs(),
+                remaining: 0,
+                reset: wait,
+                retry_after: wait,
+            };
+        }
+
+        let bucket = buckets.get_or_insert_mut(id, || Bucket {
+            tokens: capacity,
+            updated: now,
+            capacity,
+            rate,
+        });
+        bucket.refill(now);
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let tokens = bucket.tokens;
+        drop(buckets);
+
+        let secs = |missing: f64| Duration::from_secs_f64((missing.max(0.0) / rate).ceil());
+        let outcome = Outcome {
+            allowed,
+            limit: limit.requests,
+            period_secs: period.as_secs(),
+            remaining: tokens as u32,
+            reset: secs(capacity - tokens),
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                secs(1.0 - tokens)
+            },
+        };
+
+        if !allowed {
+            debug!(scope = %scope.replace('\n', "/"), key = %key, retry_after = outcome.retry_after.as_secs(), "rate_limited");
+        }
+        outcome
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        self.tokens = self.tokens_at(now);
+        self.updated = now;
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * self.rate).min(self.capacity)
+    }
+}
+
+// drop a bucket that has refilled completely to make room; when none of the
+// oldest has, how long until the first of them will
+fn evict(buckets: &mut LruCache<String, Bucket>, now: Instant) -> Result<(), Duration> {
+    let mut wait = Duration::MAX;
+    let mut full = None;
+    for (id, bucket) in buckets.iter().rev().take(EVICT_SCAN) {
+        let missing = bucket.capacity - bucket.tokens_at(now);
+        if missing <= 0.0 {
+            full = Some(id.clone());
+            break;
+        }
+        wait = wait.min(Duration::from_secs_f64((missing / bucket.rate).ceil()));
+    }
+
+    match full {
+        Some(id) => {
+            buckets.pop(&id);
+            Ok(())
+        }
+        None => Err(wait),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ratelimit::RateLimitKey;
+
+    #[test]
+    fn new_clients_do_not_reset_throttled_ones() {
+        let limiter = RateLimiter::new(&RateLimits { max_keys: 1 });
+        let limit = RateLimit {
+            requests: 1,
+            period_secs: 3600,
+            burst: None,
+            key: RateLimitKey::Ip,
+        };
+
+        assert!(limiter.check("route", "a", &limit).allowed);
+        assert!(!limiter.check("route", "a", &limit).allowed);
+
+        // no room: b waits for a's bucket to refill rather than taking its place
+        let b = limiter.check("route", "b", &limit);
+        assert!(!b.allowed);
+        assert!(b.retry_after > Duration::from_secs(3000));
+        assert!(!limiter.check("route", "a", &limit).allowed);
+    }
+
+    #[test]
+    fn refilled_buckets_make_room() {
+        let limiter = RateLimiter::new(&RateLimits { max_keys: 1 });
+        let limit = RateLimit {
+            requests: 2,
+            period_secs: 3600,
+            burst: None,
+            key: RateLimitKey::Ip,
+        };
+
+        assert!(limiter.check("route", "a", &limit).allowed);
+        // once a has refilled its bucket is as good as a new one
+        let mut buckets = limiter.buckets.lock().unwrap();
+        buckets.peek_mut("route\na").unwrap().tokens = 2.0;
+        drop(buckets);
+
+        assert!(limiter.check("route", "b", &limit).allowed);
+        assert!(!limiter.buckets.lock().unwrap().contains("route\na"));
+    }
+}
@@ -1 +1,27 @@
-// todo!
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("failed to start interpreter '{interpreter}': {source}")]
+    Spawn {
+        interpreter: String,
+        source: io::Error,
+    },
+
+    #[error("script i/o failed: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid cgi response: {0}")]
+    InvalidResponse(String),
+
+    #[error("script timed out")]
+    Timeout,
+
+    #[error(transparent)]
+    Stream(#[from] h3::error::StreamError),
+
+    #[error(transparent)]
+    Body(#[from] crate::http::body::error::BodyError),
+}
@@ -1,2 +1,321 @@
-// todo!
 mod error;
+
+pub use error::ScriptError;
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use h3::error::Code;
+use h3::server::RequestStream;
+use http::header::{self, HeaderName, HeaderValue};
+use http::{Request, Response, StatusCode};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::APP_NAME;
+use crate::config::Scripting;
+use crate::http::body::Body;
+use crate::http::response::{Head, send_head};
+
+// scripts run with a cleared environment, this is all they get for PATH
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+const MAX_HEADER_BLOCK: usize = 64 * 1024;
+const READ_CHUNK: usize = 16 * 1024;
+
+// connection-specific headers a script may print but http3 doesn't allow
+const HOP_BY_HOP: [&str; 4] = ["connection", "keep-alive", "transfer-encoding", "upgrade"];
+
+/// caps how many scripts run at once
+pub struct Scripts {
+    permits: Semaphore,
+}
+
+impl Scripts {
+    pub fn new(config: &Scripting) -> Self {
+        Self {
+            permits: Semaphore::new(config.max_processes.max(1)),
+        }
+    }
+}
+
+/// what a script is told about its request, besides the headers
+pub struct CgiRequest<'a> {
+    pub req: &'a Request<()>,
+    pub remote: SocketAddr,
+    pub script_name: &'a str,
+    pub path_info: &'a str,
+    pub server_port: u16,
+}
+
+/// run `interpreter script` as an rfc 3875 cgi script: request metadata goes in
+/// the environment, the body on stdin, and the header block plus body printed on
+/// stdout becomes the response. stderr ends up in the logs.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    scripts: &Scripts,
+    cgi: &CgiRequest<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    body: &mut Body,
+    script: &Path,
+    interpreter: &str,
+    timeout: Duration,
+) -> Result<(), ScriptError> {
+    let result = tokio::time::timeout(
+        timeout,
+        execute(scripts, cgi, stream, head, body, script, interpreter),
+    )
+    .await;
+
+    match result {
+        Ok(result) => result,
+        Err(_) => {
+            // the child is killed when the timed out future is dropped
+            warn!(
+                script = %script.display(),
+                timeout_secs = timeout.as_secs(),
+                "script_timed_out"
+            );
+
+            if head.sent() {
+                // too late for an error page, cut the response short
+                stream.stop_stream(Code::H3_INTERNAL_ERROR);
+                return Ok(());
+            }
+            Err(ScriptError::Timeout)
+        }
+    }
+}
+
+async fn execute(
+    scripts: &Scripts,
+    cgi: &CgiRequest<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    body: &mut Body,
+    script: &Path,
+    interpreter: &str,
+) -> Result<(), ScriptError> {
+    let _permit = scripts
+        .permits
+        .acquire()
+        .await
+        .map_err(|_| ScriptError::Io(std::io::Error::other("script slots closed")))?;
+
+    let mut command = Command::new(interpreter);
+    command
+        .arg(script)
+        .env_clear()
+        .envs(cgi_env(cgi, script))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    if let Some(dir) = script.parent().filter(|d| !d.as_os_str().is_empty()) {
+        command.current_dir(dir);
+    }
+
+    let mut child = command.spawn().map_err(|e| ScriptError::Spawn {
+        interpreter: interpreter.to_string(),
+        source: e,
+    })?;
+
+    debug!(script = %script.display(), pid = ?child.id(), "script_started");
+
+    let (Some(stdin), Some(stdout), Some(stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        return Err(ScriptError::Io(std::io::Error::other(
+            "missing script pipes",
+        )));
+    };
+
+    tokio::spawn(log_stderr(stderr, script.display().to_string()));
+
+    // the body is fed while the header block is read; output is only
+    // relayed once the script had the chance to consume all of its input
+    let mut stdout = BufReader::new(stdout);
+    let (fed, response) = tokio::join!(feed_stdin(stream, body, stdin), read_head(&mut stdout));
+    fed?;
+    let response = response?;
+
+    let status = response.status();
+    send_head(stream, head, response).await?;
+
+    loop {
+        let mut buf = BytesMut::with_capacity(READ_CHUNK);
+        if stdout.read_buf(&mut buf).await? == 0 {
+            break;
+        }
+        stream.send_data(buf.freeze()).await?;
+    }
+    stream.finish().await?;
+
+    let exit = child.wait().await?;
+    if !exit.success() {
+        warn!(script = %script.display(), status = %exit, "script_exit_status");
+    }
+
+    debug!(script = %script.display(), status = status.as_u16(), "script_complete");
+    Ok(())
+}
+
+async fn feed_stdin(
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    body: &mut Body,
+    mut stdin: ChildStdin,
+) -> Result<(), ScriptError> {
+    while let Some(data) = body.data(stream).await? {
+        if stdin.write_all(&data).await.is_err() {
+            // the script closed stdin, it doesn't want the rest
+            break;
+        }
+    }
+
+    // dropping stdin signals eof
+    Ok(())
+}
+
+async fn read_head<R: AsyncRead + Unpin>(
+    stdout: &mut BufReader<R>,
+) -> Result<Response<()>, ScriptError> {
+    let invalid = |reason: String| ScriptError::InvalidResponse(reason);
+
+    let mut builder = Response::builder();
+    let mut status = None;
+    let mut has_location = false;
+    let mut total = 0;
+
+    loop {
+        let mut line = Vec::new();
+        let n = stdout.read_until(b'\n', &mut line).await?;
+        if n == 0 {
+            return Err(invalid("output ended inside the header block".into()));
+        }
+
+        total += n;
+        if total > MAX_HEADER_BLOCK {
+            return Err(invalid("header block too large".into()));
+        }
+
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("malformed header line '{line}'")))?;
+        let (name, value) = (name.trim(), value.trim());
+
+        if name.eq_ignore_ascii_case("status") {
+            let code = value.split_whitespace().next().unwrap_or_default();
+            status = Some(
+                StatusCode::from_bytes(code.as_bytes())
+                    .map_err(|_| invalid(format!("bad status '{value}'")))?,
+            );
+            continue;
+        }
+
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| invalid(format!("bad header name '{name}'")))?;
+        if HOP_BY_HOP.contains(&name.as_str()) {
+            continue;
+        }
+        has_location |= name == header::LOCATION;
+
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| invalid(format!("bad value for header '{name}'")))?;
+        builder = builder.header(name, value);
+    }
+
+    // rfc 3875 6.2.3: a location without a status is a redirect
+    let status = status.unwrap_or(if has_location {
+        StatusCode::FOUND
+    } else {
+        StatusCode::OK
+    });
+
+    builder
+        .status(status)
+        .body(())
+        .map_err(|e| invalid(e.to_string()))
+}
+
+async fn log_stderr<R: AsyncRead + Unpin>(stderr: R, script: String) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        warn!(script = %script, line = %line, "script_stderr");
+    }
+}
+
+fn cgi_env(cgi: &CgiRequest<'_>, script: &Path) -> BTreeMap<String, String> {
+    let req = cgi.req;
+    let uri = req.uri();
+
+    let mut env = BTreeMap::new();
+    let mut set = |name: &str, value: String| {
+        env.insert(name.to_string(), value);
+    };
+
+    set("GATEWAY_INTERFACE", "CGI/1.1".into());
+    set("SERVER_PROTOCOL", "HTTP/3".into());
+    set(
+        "SERVER_SOFTWARE",
+        format!("{APP_NAME}/{}", env!("CARGO_PKG_VERSION")),
+    );
+    set("SERVER_NAME", uri.host().unwrap_or_default().to_string());
+    set("SERVER_PORT", cgi.server_port.to_string());
+    set("REQUEST_METHOD", req.method().to_string());
+    set(
+        "REQUEST_URI",
+        uri.path_and_query()
+            .map_or("/", |pq| pq.as_str())
+            .to_string(),
+    );
+    set("QUERY_STRING", uri.query().unwrap_or_default().to_string());
+    set("SCRIPT_NAME", cgi.script_name.to_string());
+    set("SCRIPT_FILENAME", script.display().to_string());
+    set("PATH_INFO", cgi.path_info.to_string());
+    set("REMOTE_ADDR", cgi.remote.ip().to_canonical().to_string());
+    set("REMOTE_PORT", cgi.remote.port().to_string());
+    set("HTTPS", "on".into());
+    set("PATH", DEFAULT_PATH.into());
+
+    // h3 carries the host in :authority, scripts expect it as a header
+    if let Some(authority) = uri.authority() {
+        set("HTTP_HOST", authority.to_string());
+    }
+
+    for (name, value) in req.headers() {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+
+        let var = match name {
+            n if n == header::CONTENT_TYPE => "CONTENT_TYPE".to_string(),
+            n if n == header::CONTENT_LENGTH => "CONTENT_LENGTH".to_string(),
+            // httpoxy: a `proxy` header must never become HTTP_PROXY
+            n if n.as_str() == "proxy" => continue,
+            n => format!("HTTP_{}", n.as_str().to_ascii_uppercase().replace('-', "_")),
+        };
+
+        env.entry(var)
+            .and_modify(|v: &mut String| {
+                v.push_str(", ");
+                v.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    env
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use tokio::fs;
+
+use crate::helpers::path;
+
+pub struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// entries of `dir` as a listing shows them: directories first, then by name.
+/// hidden entries are left out.
+pub async fn read(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+
+        // follows symlinks; dangling ones are skipped
+        let Ok(meta) = fs::metadata(entry.path()).await else {
+            continue;
+        };
+
+        entries.push(Entry {
+            name,
+            is_dir: meta.is_dir(),
+            size: meta.len(),
+            modified: meta.modified().ok().map(DateTime::<Utc>::from),
+        });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// nginx-style html listing; `uri_path` is the (encoded) request path of the directory
+pub fn html(uri_path: &str, entries: &[Entry]) -> String {
+    let title = escape_html(&path::percent_decode(uri_path).unwrap_or_else(|| uri_path.into()));
+
+    let mut out = format!(
+        "<html>\n<head><title>Index of {title}</title></head>\n<body>\n\
+         <h1>Index of {title}</h1><hr><pre><a href=\"../\">../</a>\n"
+    );
+
+    for entry in entries {
+        let slash = if entry.is_dir { "/" } else { "" };
+        let href = format!("{}{slash}", path::percent_encode(&entry.name));
+        let name = format!("{}{slash}", entry.name);
+        let modified = entry
+            .modified
+            .map(|m| m.format("%d-%b-%Y %H:%M").to_string())
+            .unwrap_or_default();
+        let size = if entry.is_dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+
+        // name column is padded to 50 characters, like nginx does
+        let padding = 51usize.saturating_sub(name.chars().count());
+        let _ = writeln!(
+            out,
+            "<a href=\"{href}\">{}</a>{:padding$}{modified:<17} {size:>19}",
+            escape_html(&name),
+            ""
+        );
+    }
+
+    out.push_str("</pre><hr></body>\n</html>\n");
+    out
+}
+
+/// nginx-style json listing
+pub fn json(entries: &[Entry]) -> String {
+    let mut out = String::from("[\n");
+
+    for (i, entry) in entries.iter().enumerate() {
+        let kind = if entry.is_dir { "directory" } else { "file" };
+        let _ = write!(
+            out,
+            "{{ \"name\":\"{}\", \"type\":\"{kind}\"",
+            escape_json(&entry.name)
+        );
+        if let Some(modified) = entry.modified {
+            let _ = write!(
+                out,
+                ", \"mtime\":\"{}\"",
+                modified.format("%a, %d %b %Y %H:%M:%S GMT")
+            );
+        }
+        if !entry.is_dir {
+            let _ = write!(out, ", \"size\":{}", entry.size);
+        }
+        out.push_str(if i + 1 < entries.len() {
+            " },\n"
+        } else {
+            " }\n"
+        });
+    }
+
+    out.push_str("]\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_json(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
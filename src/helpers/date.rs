@@ -0,0 +1,25 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+
+/// imf-fixdate, the one date format http senders use (rfc 9110 5.6.7)
+pub fn format(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// parse an imf-fixdate; obsolete formats are not worth the trouble
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let time = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let secs = u64::try_from(time.timestamp()).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// `time` cut to whole seconds, the precision http dates have
+pub fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
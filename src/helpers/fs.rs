@@ -1,19 +1,105 @@
-use std::path::Path;
-use tokio::fs;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::helpers::path;
 
 #[derive(Debug)]
 pub enum StaticRead {
-    Ok(Vec<u8>),
+    Ok {
+        file: File,
+        len: u64,
+        modified: Option<SystemTime>,
+    },
     NotFound,
     Forbidden,
     Error,
 }
 
-pub async fn read(path: &Path) -> StaticRead {
-    match fs::read(path).await {
-        Ok(data) => StaticRead::Ok(data),
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => StaticRead::NotFound,
-        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => StaticRead::Forbidden,
+/// open a file for streaming; its metadata is taken once, here
+pub async fn open(path: &Path) -> StaticRead {
+    let opened = async {
+        let file = File::open(path).await?;
+        let meta = file.metadata().await?;
+        Ok::<_, std::io::Error>((file, meta))
+    };
+
+    match opened.await {
+        Ok((_, meta)) if meta.is_dir() => StaticRead::NotFound,
+        Ok((file, meta)) => StaticRead::Ok {
+            file,
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => StaticRead::NotFound,
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => StaticRead::Forbidden,
         Err(_) => StaticRead::Error,
     }
 }
+
+/// up to `limit` bytes from the start of `file`, which is rewound afterwards
+pub async fn sniff(file: &mut File, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(limit);
+    (&mut *file)
+        .take(limit as u64)
+        .read_to_end(&mut head)
+        .await?;
+    file.seek(SeekFrom::Start(0)).await?;
+    Ok(head)
+}
+
+/// what a request path below a static root points at
+#[derive(Debug)]
+pub enum Resolved {
+    File(PathBuf),
+    Dir(PathBuf),
+    NotFound,
+    Forbidden,
+    Error,
+}
+
+/// map `rest`, still percent-encoded, to a path below `root`. `..` and symlinks
+/// leading out of `root` are refused. a root that is a file is what every path
+/// below it maps to.
+pub async fn resolve(root: &Path, rest: &str) -> Resolved {
+    let root = match fs::canonicalize(root).await {
+        Ok(root) => root,
+        Err(e) => return resolve_error(&e),
+    };
+
+    match fs::metadata(&root).await {
+        Ok(meta) if !meta.is_dir() => return Resolved::File(root),
+        Ok(_) => {}
+        Err(e) => return resolve_error(&e),
+    }
+
+    let Some(rest) = path::percent_decode(rest).filter(|r| path::is_safe_relative(r)) else {
+        return Resolved::Forbidden;
+    };
+
+    let target = match fs::canonicalize(root.join(rest)).await {
+        Ok(target) => target,
+        Err(e) => return resolve_error(&e),
+    };
+
+    // a symlink somewhere on the way may still point elsewhere
+    if !target.starts_with(&root) {
+        return Resolved::Forbidden;
+    }
+
+    match fs::metadata(&target).await {
+        Ok(meta) if meta.is_dir() => Resolved::Dir(target),
+        Ok(_) => Resolved::File(target),
+        Err(e) => resolve_error(&e),
+    }
+}
+
+fn resolve_error(e: &std::io::Error) -> Resolved {
+    match e.kind() {
+        ErrorKind::NotFound | ErrorKind::NotADirectory => Resolved::NotFound,
+        ErrorKind::PermissionDenied => Resolved::Forbidden,
+        _ => Resolved::Error,
+    }
+}
@@ -0,0 +1,38 @@
+/// host name of an authority or sni value as hosts are keyed: lowercase,
+/// without port, brackets or a trailing dot
+pub fn normalize(name: &str) -> String {
+    let host = match name.strip_prefix('[') {
+        // ipv6 literal, maybe with a port after the bracket
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => name.rsplit_once(':').map_or(name, |(host, port)| {
+            if port.bytes().all(|b| b.is_ascii_digit()) {
+                host
+            } else {
+                name
+            }
+        }),
+    };
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// the pattern among `patterns` that names `host` (already normalized).
+/// exact names win over wildcards, and `*.example.com` covers one label only.
+pub fn find<'p>(patterns: impl IntoIterator<Item = &'p str>, host: &str) -> Option<&'p str> {
+    let mut wildcard = None;
+
+    for pattern in patterns {
+        if pattern.eq_ignore_ascii_case(host) {
+            return Some(pattern);
+        }
+        if let Some(suffix) = pattern.strip_prefix("*.")
+            && let Some((label, rest)) = host.split_once('.')
+            && !label.is_empty()
+            && rest.eq_ignore_ascii_case(suffix)
+        {
+            wildcard = Some(pattern);
+        }
+    }
+
+    wildcard
+}
@@ -0,0 +1,15 @@
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 32 hex digits naming one request, like nginx's $request_id
+pub fn request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    // each RandomState is keyed differently, so two of them give 128 unpredictable bits
+    format!(
+        "{:016x}{:016x}",
+        RandomState::new().hash_one(n),
+        RandomState::new().hash_one(n)
+    )
+}
@@ -1,3 +1,104 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// built-in extension table, after nginx's mime.types. text is taken to be
+// utf-8, as the content sniffing did before it
+const DEFAULT_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("shtml", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("xml", "text/xml; charset=utf-8"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json; charset=utf-8"),
+    ("jsonld", "application/ld+json; charset=utf-8"),
+    ("map", "application/json; charset=utf-8"),
+    ("webmanifest", "application/manifest+json; charset=utf-8"),
+    ("atom", "application/atom+xml"),
+    ("rss", "application/rss+xml"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("bin", "application/octet-stream"),
+    ("gif", "image/gif"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("svgz", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("m4a", "audio/mp4"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+];
+
+/// extension to media type table: the built-in defaults, overridden by config
+pub struct Types {
+    by_extension: HashMap<String, String>,
+}
+
+impl Types {
+    pub fn new(overrides: &HashMap<String, String>) -> Self {
+        let mut by_extension: HashMap<String, String> = DEFAULT_TYPES
+            .iter()
+            .map(|(ext, ty)| (ext.to_string(), ty.to_string()))
+            .collect();
+
+        for (ext, ty) in overrides {
+            by_extension.insert(ext.trim_start_matches('.').to_ascii_lowercase(), ty.clone());
+        }
+
+        Self { by_extension }
+    }
+
+    pub fn from_path(&self, path: &Path) -> Option<&str> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.by_extension.get(&ext).map(String::as_str)
+    }
+}
+
+// content sniffing, for files the table doesn't know
 pub fn from_bytes(data: &[u8]) -> &'static str {
     mimetype_detector::detect(data).mime()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_types_say_utf_8() {
+        let types = Types::new(&HashMap::new());
+
+        assert_eq!(
+            types.from_path(Path::new("www/index.HTML")),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(
+            types.from_path(Path::new("app.js")),
+            Some("text/javascript; charset=utf-8")
+        );
+        assert_eq!(types.from_path(Path::new("logo.png")), Some("image/png"));
+        assert_eq!(types.from_path(Path::new("README")), None);
+    }
+}
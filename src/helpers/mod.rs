@@ -1,2 +1,10 @@
+pub mod autoindex;
+pub mod date;
 pub mod fs;
+pub mod host;
+pub mod id;
 pub mod mime;
+pub mod net;
+pub mod path;
+pub mod range;
+pub mod x509;
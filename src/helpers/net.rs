@@ -0,0 +1,20 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// the first `v4` or `v6` bits of `ip`, the rest zeroed: the address block it
+/// belongs to. ipv4-mapped ipv6 addresses count as ipv4.
+pub fn prefix(ip: IpAddr, v4: u8, v6: u8) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(v4.min(32)))
+                .unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(ip.to_bits() & mask))
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(v6.min(128)))
+                .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(ip.to_bits() & mask))
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::http::router::{self, Params};
+
+/// percent-decode a piece of uri path; `None` if the result isn't utf-8
+pub fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(out).ok()
+}
+
+/// a relative path that stays below whatever it gets joined to
+pub fn is_safe_relative(path: &str) -> bool {
+    !path.contains('\0')
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// fill route parameters into a file path template. parameters are decoded and
+/// must not climb out of where they are placed, otherwise there is no file.
+pub fn expand_file(template: &Path, params: &[(String, String)]) -> Option<PathBuf> {
+    let Some(text) = template.to_str().filter(|t| t.contains('{')) else {
+        return Some(template.to_path_buf());
+    };
+
+    let mut decoded = Params::with_capacity(params.len());
+    for (name, value) in params {
+        let value = percent_decode(value)?;
+        if !is_safe_relative(&value) {
+            return None;
+        }
+        decoded.push((name.clone(), value));
+    }
+
+    Some(PathBuf::from(router::expand(text, &decoded)))
+}
+
+/// fill route parameters into a uri path template. parameters stay encoded, but
+/// decoded they must not climb out of where they are placed or hold empty
+/// segments, other than a trailing slash; otherwise there is no path.
+pub fn expand_uri(template: &str, params: &[(String, String)]) -> Option<String> {
+    for (_, value) in params {
+        let decoded = percent_decode(value)?;
+        let inner = decoded.strip_suffix('/').unwrap_or(&decoded);
+        if !is_safe_relative(&decoded) || (!inner.is_empty() && inner.split('/').any(str::is_empty))
+        {
+            return None;
+        }
+    }
+
+    Some(router::expand(template, params))
+}
+
+/// a path on this host that can't be read as "//host/...": leading slashes
+/// and backslashes collapse to one '/'
+pub fn local_path(path: &str) -> String {
+    match path.strip_prefix(['/', '\\']) {
+        Some(rest) if rest.starts_with(['/', '\\']) => {
+            format!("/{}", rest.trim_start_matches(['/', '\\']))
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// percent-encode one path segment, leaving only unreserved characters as they are
+pub fn percent_encode(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expand_is_one_pass() {
+        let params = params(&[("a", "{b}"), ("b", "x")]);
+        assert_eq!(router::expand("/{a}/{b}/{c}", &params), "/{b}/x/{c}");
+        assert_eq!(router::expand("{a", &params), "{a");
+        assert_eq!(router::expand("}{}{b}", &params), "}{}x");
+    }
+
+    #[test]
+    fn expand_uri_keeps_params_below_their_place() {
+        let expand = |value: &str| expand_uri("/v2/{rest}", &params(&[("rest", value)]));
+
+        assert_eq!(expand("a/b%20c").as_deref(), Some("/v2/a/b%20c"));
+        assert_eq!(expand("a/b/").as_deref(), Some("/v2/a/b/"));
+        assert_eq!(expand("").as_deref(), Some("/v2/"));
+        assert_eq!(expand("./a").as_deref(), Some("/v2/./a"));
+
+        for value in [
+            "..", "a/../..", "%2e%2e/x", "a//b", "/etc", "%2Fetc", "a%00", "%ff",
+        ] {
+            assert_eq!(expand(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn local_path_stays_on_this_host() {
+        assert_eq!(local_path("/a//b"), "/a//b");
+        assert_eq!(local_path("//evil.example/"), "/evil.example/");
+        assert_eq!(local_path("/\\evil.example"), "/evil.example");
+        assert_eq!(local_path("\\/\\evil.example"), "/evil.example");
+        assert_eq!(local_path("\\a"), "\\a");
+        assert_eq!(local_path(""), "");
+    }
+}
@@ -0,0 +1,81 @@
+use std::ops::Range;
+
+// more ranges than this in one request are answered with the whole body
+const MAX_RANGES: usize = 16;
+
+/// how a `range` header applies to a body of a given length
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    /// no usable range header: send everything
+    Full,
+    /// sorted, non-overlapping byte ranges
+    Partial(Vec<Range<u64>>),
+    Unsatisfiable,
+}
+
+/// parse a `range` header value (rfc 9110 14.2) against a body of `len` bytes.
+/// headers that don't parse are ignored, as the rfc allows.
+pub fn parse(value: &str, len: u64) -> Ranges {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return Ranges::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return Ranges::Full;
+        };
+
+        let range = match (first.trim(), last.trim()) {
+            // suffix: the last n bytes
+            ("", suffix) => {
+                let Ok(suffix) = suffix.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+                len.saturating_sub(suffix)..len
+            }
+            (first, last) => {
+                let Ok(first) = first.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+                let last = match last {
+                    "" => len.saturating_sub(1),
+                    last => match last.parse::<u64>() {
+                        Ok(last) if last >= first => last.min(len.saturating_sub(1)),
+                        _ => return Ranges::Full,
+                    },
+                };
+                first..last.saturating_add(1)
+            }
+        };
+
+        // unsatisfiable ones are dropped; only all of them failing is an error
+        if range.start < len && range.start < range.end {
+            ranges.push(range);
+        }
+    }
+
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+
+    // overlapping and adjacent ranges are sent once
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    if merged.len() > MAX_RANGES {
+        return Ranges::Full;
+    }
+    Ranges::Partial(merged)
+}
+
+/// `content-range` value for `range` of a body of `len` bytes
+pub fn content_range(range: &Range<u64>, len: u64) -> String {
+    format!("bytes {}-{}/{len}", range.start, range.end - 1)
+}
@@ -0,0 +1,284 @@
+use std::fmt::Write;
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use x509_parser::asn1_rs::{Any, Tag, ToDer};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate, X509Name};
+
+/// end of the validity period of a der encoded certificate (rfc 5280 4.1)
+pub fn not_after(cert: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+}
+
+/// subject distinguished name, rfc 4514 style: "CN=client,O=Example"
+pub fn subject(cert: &[u8]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    Some(distinguished_name(cert.subject()))
+}
+
+/// subject alternative names, as "DNS:name", "IP:addr", "URI:uri" or "email:addr"
+pub fn subject_alt_names(cert: &[u8]) -> Vec<String> {
+    let Ok((_, cert)) = X509Certificate::from_der(cert) else {
+        return Vec::new();
+    };
+    let Ok(Some(names)) = cert.subject_alternative_name() else {
+        return Vec::new();
+    };
+
+    let mut out = Vec::new();
+    for name in &names.value.general_names {
+        match name {
+            GeneralName::RFC822Name(email) => out.push(format!("email:{email}")),
+            GeneralName::DNSName(dns) => out.push(format!("DNS:{dns}")),
+            GeneralName::URI(uri) => out.push(format!("URI:{uri}")),
+            GeneralName::IPAddress(value) => {
+                let ip = match value.len() {
+                    4 => <[u8; 4]>::try_from(*value).ok().map(IpAddr::from),
+                    16 => <[u8; 16]>::try_from(*value).ok().map(IpAddr::from),
+                    _ => None,
+                };
+                if let Some(ip) = ip {
+                    out.push(format!("IP:{ip}"));
+                }
+            }
+            // other name forms aren't shown
+            _ => {}
+        }
+    }
+    out
+}
+
+fn distinguished_name(name: &X509Name) -> String {
+    // rfc 4514 lists the most specific part first, and joins the attributes
+    // of a multi-valued rdn with '+'
+    let mut rdns: Vec<String> = name
+        .iter()
+        .map(|rdn| {
+            rdn.iter()
+                .map(|attribute| {
+                    let oid = attribute.attr_type();
+                    let name = attribute_name(oid.as_bytes())
+                        .map_or_else(|| oid.to_id_string(), str::to_string);
+                    format!("{name}={}", attribute_value(attribute.attr_value()))
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect();
+    rdns.reverse();
+    rdns.join(",")
+}
+
+fn attribute_value(value: &Any) -> String {
+    let data = value.data;
+    let text = match value.header.tag() {
+        Tag::Utf8String | Tag::PrintableString | Tag::Ia5String | Tag::NumericString => {
+            std::str::from_utf8(data).ok().map(str::to_string)
+        }
+        // ucs-2, big endian
+        Tag::BmpString => {
+            let units = data
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]));
+            data.len()
+                .is_multiple_of(2)
+                .then(|| {
+                    char::decode_utf16(units)
+                        .collect::<Result<String, _>>()
+                        .ok()
+                })
+                .flatten()
+        }
+        // ucs-4, big endian
+        Tag::UniversalString => data
+            .len()
+            .is_multiple_of(4)
+            .then(|| {
+                data.chunks_exact(4)
+                    .map(|c| char::from_u32(u32::from_be_bytes([c[0], c[1], c[2], c[3]])))
+                    .collect::<Option<String>>()
+            })
+            .flatten(),
+        // t.61 in theory; in practice certificates put latin-1 there
+        Tag::TeletexString => Some(data.iter().map(|&b| char::from(b)).collect()),
+        _ => None,
+    };
+
+    match text {
+        Some(text) => escape_dn(&text),
+        // anything else as '#' and the hex of its der encoding (rfc 4514 2.4)
+        None => {
+            let der = value.to_der_vec().unwrap_or_default();
+            der.iter().fold(String::from("#"), |mut out, b| {
+                let _ = write!(out, "{b:02x}");
+                out
+            })
+        }
+    }
+}
+
+fn attribute_name(oid: &[u8]) -> Option<&'static str> {
+    Some(match oid {
+        [0x55, 0x04, 0x03] => "CN",
+        [0x55, 0x04, 0x05] => "serialNumber",
+        [0x55, 0x04, 0x06] => "C",
+        [0x55, 0x04, 0x07] => "L",
+        [0x55, 0x04, 0x08] => "ST",
+        [0x55, 0x04, 0x09] => "street",
+        [0x55, 0x04, 0x0a] => "O",
+        [0x55, 0x04, 0x0b] => "OU",
+        [0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01] => "UID",
+        [0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19] => "DC",
+        [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01] => "emailAddress",
+        _ => return None,
+    })
+}
+
+fn escape_dn(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        let edge = (i == 0 && matches!(c, ' ' | '#')) || (i == last && c == ' ');
+        if edge || matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rcgen::string::{BmpString, UniversalString};
+    use rcgen::{
+        CertificateParams, DistinguishedName, DnType, DnValue, KeyPair, SanType, date_time_ymd,
+    };
+
+    fn cert(params: CertificateParams) -> Vec<u8> {
+        let key = KeyPair::generate().unwrap();
+        params.self_signed(&key).unwrap().der().to_vec()
+    }
+
+    #[test]
+    fn not_after_reads_both_time_types() {
+        // utctime up to 2049, generalizedtime from 2050 on (rfc 5280 4.1.2.5)
+        for year in [1999, 2030, 2049, 2050, 2100] {
+            let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+            params.not_after = date_time_ymd(year, 6, 15);
+
+            assert_eq!(
+                not_after(&cert(params)),
+                Utc.with_ymd_and_hms(year, 6, 15, 0, 0, 0).single(),
+                "{year}"
+            );
+        }
+    }
+
+    #[test]
+    fn not_after_of_a_large_certificate() {
+        // lengths past 127 bytes take the long form, past 255 two length bytes
+        let names: Vec<String> = (0..40).map(|i| format!("host-{i}.example.com")).collect();
+        let mut params = CertificateParams::new(names).unwrap();
+        params.not_after = date_time_ymd(2040, 1, 2);
+
+        let der = cert(params);
+        assert!(der.len() > 1024);
+        assert_eq!(
+            not_after(&der),
+            Utc.with_ymd_and_hms(2040, 1, 2, 0, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn not_after_of_garbage() {
+        assert_eq!(not_after(b""), None);
+        assert_eq!(not_after(&[0x30, 0x82, 0xff]), None);
+    }
+
+    #[test]
+    fn subject_of_a_certificate() {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CountryName, "DE");
+        params.distinguished_name.push(
+            DnType::OrganizationName,
+            DnValue::BmpString(BmpString::try_from("Bücher & Söhne").unwrap()),
+        );
+        params.distinguished_name.push(
+            DnType::OrganizationalUnitName,
+            DnValue::UniversalString(UniversalString::try_from("Größen").unwrap()),
+        );
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "client, the first");
+
+        assert_eq!(
+            subject(&cert(params)).as_deref(),
+            Some("CN=client\\, the first,OU=Größen,O=Bücher & Söhne,C=DE")
+        );
+        assert_eq!(subject(b"not a certificate"), None);
+    }
+
+    #[test]
+    fn subject_with_multi_valued_and_legacy_attributes() {
+        fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+            let mut out = vec![tag, u8::try_from(content.len()).unwrap()];
+            out.extend_from_slice(content);
+            out
+        }
+        fn attribute(oid: &[u8], value: Vec<u8>) -> Vec<u8> {
+            der(0x30, &[der(0x06, oid), value].concat())
+        }
+
+        let cn = attribute(&[0x55, 0x04, 0x03], der(0x0c, b"a"));
+        let uid = attribute(
+            &[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01],
+            der(0x0c, b"b"),
+        );
+        // teletexstring holding latin-1
+        let o = attribute(&[0x55, 0x04, 0x0a], der(0x14, b"caf\xe9"));
+        // givenName as an integer, which has no string form
+        let other = attribute(&[0x55, 0x04, 0x2a], der(0x02, &[0x05]));
+
+        let name = der(
+            0x30,
+            &[
+                der(0x31, &[cn, uid].concat()),
+                der(0x31, &o),
+                der(0x31, &other),
+            ]
+            .concat(),
+        );
+        let (_, name) = X509Name::from_der(&name).unwrap();
+        assert_eq!(
+            distinguished_name(&name),
+            "2.5.4.42=#020105,O=café,CN=a+UID=b"
+        );
+    }
+
+    #[test]
+    fn subject_alt_names_of_a_certificate() {
+        let mut params = CertificateParams::new(vec!["a.test".into()]).unwrap();
+        params.subject_alt_names.extend([
+            SanType::IpAddress("192.0.2.1".parse().unwrap()),
+            SanType::IpAddress("2001:db8::1".parse().unwrap()),
+            SanType::URI("spiffe://test/client".try_into().unwrap()),
+            SanType::Rfc822Name("client@a.test".try_into().unwrap()),
+        ]);
+
+        assert_eq!(
+            subject_alt_names(&cert(params)),
+            [
+                "DNS:a.test",
+                "IP:192.0.2.1",
+                "IP:2001:db8::1",
+                "URI:spiffe://test/client",
+                "email:client@a.test",
+            ]
+        );
+        assert!(subject_alt_names(&cert(CertificateParams::default())).is_empty());
+    }
+}
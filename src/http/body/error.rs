@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BodyError {
+    #[error("request body larger than {limit} bytes")]
+    TooLarge { limit: u64 },
+
+    #[error("request body of {received} bytes doesn't match content-length {expected}")]
+    LengthMismatch { expected: u64, received: u64 },
+
+    #[error("invalid content-length")]
+    InvalidLength,
+
+    #[error(transparent)]
+    Stream(#[from] h3::error::StreamError),
+}
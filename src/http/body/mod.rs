@@ -0,0 +1,103 @@
+pub mod error;
+
+use bytes::{Buf, Bytes, BytesMut};
+use h3::server::RequestStream;
+use http::Request;
+use http::header::{self, HeaderMap};
+
+use error::BodyError;
+
+/// reads a request body off its stream, chunk by chunk or all at once, and
+/// holds it to the size limit and to the content-length the client announced
+pub struct Body {
+    limit: Option<u64>,
+    expected: Option<u64>,
+    received: u64,
+}
+
+impl Body {
+    /// fails right away when the announced length is already over `limit`
+    pub fn new(req: &Request<()>, limit: Option<u64>) -> Result<Self, BodyError> {
+        let expected = match req.headers().get(header::CONTENT_LENGTH) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .ok_or(BodyError::InvalidLength)?,
+            ),
+            None => None,
+        };
+
+        if let (Some(limit), Some(expected)) = (limit, expected)
+            && expected > limit
+        {
+            return Err(BodyError::TooLarge { limit });
+        }
+
+        Ok(Self {
+            limit,
+            expected,
+            received: 0,
+        })
+    }
+
+    /// next chunk of the body, `None` once it is complete
+    pub async fn data(
+        &mut self,
+        stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    ) -> Result<Option<Bytes>, BodyError> {
+        let Some(mut chunk) = stream.recv_data().await? else {
+            if let Some(expected) = self.expected
+                && expected != self.received
+            {
+                return Err(self.mismatch());
+            }
+            return Ok(None);
+        };
+
+        let data = chunk.copy_to_bytes(chunk.remaining());
+        self.received += data.len() as u64;
+
+        if let Some(limit) = self.limit
+            && self.received > limit
+        {
+            return Err(BodyError::TooLarge { limit });
+        }
+        if self
+            .expected
+            .is_some_and(|expected| self.received > expected)
+        {
+            return Err(self.mismatch());
+        }
+
+        Ok(Some(data))
+    }
+
+    /// trailers, once `data` returned `None`
+    pub async fn trailers(
+        &mut self,
+        stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    ) -> Result<Option<HeaderMap>, BodyError> {
+        Ok(stream.recv_trailers().await?)
+    }
+
+    /// the whole body in memory; the size limit keeps that bounded
+    pub async fn collect(
+        &mut self,
+        stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    ) -> Result<Bytes, BodyError> {
+        let mut body = BytesMut::with_capacity(self.expected.unwrap_or(0).min(64 * 1024) as usize);
+        while let Some(data) = self.data(stream).await? {
+            body.extend_from_slice(&data);
+        }
+        Ok(body.freeze())
+    }
+
+    fn mismatch(&self) -> BodyError {
+        BodyError::LengthMismatch {
+            expected: self.expected.unwrap_or_default(),
+            received: self.received,
+        }
+    }
+}
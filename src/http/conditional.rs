@@ -0,0 +1,129 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::Method;
+use http::header::{self, HeaderMap};
+
+use crate::config::validators::ETag;
+use crate::helpers::date;
+
+/// what the preconditions of a request say about answering it
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Proceed,
+    NotModified,
+    PreconditionFailed,
+}
+
+/// entity tag for a file, from its modification time and size
+pub fn etag(kind: ETag, modified: Option<SystemTime>, len: u64) -> Option<String> {
+    let since = modified?.duration_since(UNIX_EPOCH).ok()?;
+    match kind {
+        ETag::Off => None,
+        ETag::Strong => Some(format!(
+            "\"{:x}.{:x}-{len:x}\"",
+            since.as_secs(),
+            since.subsec_nanos()
+        )),
+        ETag::Weak => Some(format!("W/\"{:x}-{len:x}\"", since.as_secs())),
+    }
+}
+
+/// weak variant of `etag` for a representation derived from it, e.g. compressed
+pub fn weaken(etag: &str, suffix: &str) -> String {
+    let opaque = opaque(etag).trim_matches('"');
+    format!("W/\"{opaque}-{suffix}\"")
+}
+
+/// evaluate the preconditions of a request against the current validators of
+/// the target, in the order of rfc 9110 13.2.2
+pub fn evaluate(
+    method: &Method,
+    headers: &HeaderMap,
+    etag: Option<&str>,
+    modified: Option<SystemTime>,
+) -> Outcome {
+    let modified = modified.map(date::truncate);
+
+    if let Some(if_match) = header_str(headers, header::IF_MATCH) {
+        if !matches_any(if_match, etag, true) {
+            return Outcome::PreconditionFailed;
+        }
+    } else if let Some(since) =
+        header_str(headers, header::IF_UNMODIFIED_SINCE).and_then(date::parse)
+        && modified.is_some_and(|m| m > since)
+    {
+        return Outcome::PreconditionFailed;
+    }
+
+    let safe = matches!(*method, Method::GET | Method::HEAD);
+
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        if matches_any(if_none_match, etag, false) {
+            return if safe {
+                Outcome::NotModified
+            } else {
+                Outcome::PreconditionFailed
+            };
+        }
+    } else if safe
+        && let Some(since) = header_str(headers, header::IF_MODIFIED_SINCE).and_then(date::parse)
+        && modified.is_some_and(|m| m <= since)
+    {
+        return Outcome::NotModified;
+    }
+
+    Outcome::Proceed
+}
+
+/// whether an `if-range` value still names the current representation
+pub fn if_range_holds(
+    headers: &HeaderMap,
+    etag: Option<&str>,
+    modified: Option<SystemTime>,
+) -> bool {
+    let Some(value) = header_str(headers, header::IF_RANGE) else {
+        return true;
+    };
+
+    // entity tags are compared strongly, dates must match exactly
+    if value.starts_with('"') || value.starts_with("W/") {
+        return etag.is_some_and(|etag| strong_eq(value, etag));
+    }
+
+    match (date::parse(value), modified) {
+        (Some(since), Some(modified)) => date::truncate(modified) == since,
+        _ => false,
+    }
+}
+
+// `*` or a comma separated list of entity tags
+fn matches_any(list: &str, etag: Option<&str>, strong: bool) -> bool {
+    // callers only evaluate preconditions for targets that exist
+    if list.trim() == "*" {
+        return true;
+    }
+
+    let Some(etag) = etag else {
+        return false;
+    };
+
+    list.split(',').map(str::trim).any(|tag| {
+        if strong {
+            strong_eq(tag, etag)
+        } else {
+            opaque(tag) == opaque(etag)
+        }
+    })
+}
+
+fn strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
@@ -0,0 +1,89 @@
+use http::header::{self, HeaderMap};
+
+/// content codings motmot can send, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    /// name in `accept-encoding` and `content-encoding`
+    pub fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// suffix of a precompressed sibling file
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// the best of `offered` that `accept-encoding` allows (rfc 9110 12.5.3).
+/// ties go to the order of `offered`; no header means no encoding.
+pub fn negotiate(headers: &HeaderMap, offered: &[Encoding]) -> Option<Encoding> {
+    let accept: Vec<(String, f32)> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((coding, q))
+        })
+        .collect();
+
+    let quality = |encoding: Encoding| {
+        accept
+            .iter()
+            .find(|(coding, _)| coding == encoding.token())
+            .or_else(|| accept.iter().find(|(coding, _)| coding == "*"))
+            .map_or(0.0, |(_, q)| *q)
+    };
+
+    offered
+        .iter()
+        .copied()
+        .map(|encoding| (encoding, quality(encoding)))
+        .filter(|(_, q)| *q > 0.0)
+        // max_by keeps the last of equals, so walk the preference order backwards
+        .rev()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(encoding, _)| encoding)
+}
+
+/// whether `content_type` is listed in `types`; `text/*` covers a whole type
+pub fn is_compressible(content_type: &str, types: &[String]) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let main = essence.split('/').next().unwrap_or_default();
+
+    types.iter().any(|t| match t.strip_suffix("/*") {
+        Some(prefix) => prefix.eq_ignore_ascii_case(main),
+        None => t.eq_ignore_ascii_case(&essence),
+    })
+}
@@ -0,0 +1,217 @@
+use http::Request;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::debug;
+
+use crate::config::HeaderRules;
+use crate::helpers::path;
+use crate::http::peer::Peer;
+
+/// values header rules and redirect targets can refer to as `$name`
+pub struct Vars {
+    pairs: Vec<(&'static str, String)>,
+}
+
+impl Vars {
+    pub fn new(req: &Request<()>, peer: &Peer, server_name: &str, request_id: &str) -> Self {
+        let uri = req.uri();
+        let remote = peer.remote;
+        let client = peer.client_cert();
+        let pairs = vec![
+            ("remote_addr", remote.ip().to_canonical().to_string()),
+            ("remote_port", remote.port().to_string()),
+            ("request_id", request_id.to_string()),
+            ("server_name", server_name.to_string()),
+            ("host", uri.host().unwrap_or_default().to_string()),
+            ("scheme", "https".to_string()),
+            ("request_method", req.method().to_string()),
+            // leading slashes merged, so "$uri/" never reads as "//host/"
+            (
+                "request_uri",
+                path::local_path(
+                    uri.path_and_query()
+                        .map_or_else(|| uri.path(), |pq| pq.as_str()),
+                ),
+            ),
+            ("uri", path::local_path(uri.path())),
+            ("args", uri.query().unwrap_or_default().to_string()),
+            (
+                "is_args",
+                if uri.query().is_some() { "?" } else { "" }.to_string(),
+            ),
+            // the verified client certificate, named as nginx does
+            (
+                "ssl_client_verify",
+                if client.is_some() { "SUCCESS" } else { "NONE" }.to_string(),
+            ),
+            (
+                "ssl_client_s_dn",
+                client.map(|c| c.subject.clone()).unwrap_or_default(),
+            ),
+            (
+                "ssl_client_san",
+                client.map(|c| c.sans.join(",")).unwrap_or_default(),
+            ),
+        ];
+        Self { pairs }
+    }
+
+    /// substitute `$name` in `template`; unknown names are left as they are
+    pub fn expand(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(at) = rest.find('$') {
+            out.push_str(&rest[..at]);
+            rest = &rest[at + 1..];
+
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            match self.pairs.iter().find(|(name, _)| *name == &rest[..len]) {
+                Some((_, value)) => out.push_str(value),
+                None => {
+                    out.push('$');
+                    out.push_str(&rest[..len]);
+                }
+            }
+            rest = &rest[len..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+/// header rules with their variables filled in, ready to apply to a header map
+#[derive(Debug, Default)]
+pub struct HeaderRewrite {
+    ops: Vec<Op>,
+}
+
+#[derive(Debug)]
+enum Op {
+    Remove(HeaderName),
+    Set(HeaderName, HeaderValue),
+    Add(HeaderName, HeaderValue),
+}
+
+/// a header rule that could never apply
+#[derive(Debug)]
+pub struct InvalidRule {
+    pub header: String,
+    pub reason: &'static str,
+}
+
+// hop-by-hop (rfc 9110 7.6.1) or framing headers: the server and the proxy own these
+const RESERVED: [&str; 8] = [
+    "connection",
+    "content-length",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// check `rules` once, when the config is loaded
+pub fn check_rules(rules: &HeaderRules) -> Result<(), InvalidRule> {
+    let names = rules
+        .remove_headers
+        .iter()
+        .chain(rules.set_headers.keys())
+        .chain(rules.add_headers.iter().map(|(name, _)| name));
+    for name in names {
+        let invalid = |reason| InvalidRule {
+            header: name.clone(),
+            reason,
+        };
+        let parsed = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| invalid("not a valid header name"))?;
+        if RESERVED.contains(&parsed.as_str()) {
+            return Err(invalid("hop-by-hop and framing headers can't be changed"));
+        }
+    }
+
+    let values = rules
+        .set_headers
+        .iter()
+        .chain(rules.add_headers.iter().map(|(name, value)| (name, value)));
+    for (name, value) in values {
+        // what variables expand to is checked per request, the rest of the value here
+        if HeaderValue::from_str(value).is_err() {
+            return Err(InvalidRule {
+                header: name.clone(),
+                reason: "not a valid header value",
+            });
+        }
+    }
+    Ok(())
+}
+
+impl HeaderRewrite {
+    /// `rules` in the order they apply, checked by `check_rules`. a value some
+    /// variable made invalid is skipped
+    pub fn new<'r>(rules: impl IntoIterator<Item = &'r HeaderRules>, vars: &Vars) -> Self {
+        let mut ops = Vec::new();
+
+        for rules in rules {
+            for name in &rules.remove_headers {
+                if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                    ops.push(Op::Remove(name));
+                }
+            }
+            for (name, value) in &rules.set_headers {
+                if let Some((name, value)) = header(name, value, vars) {
+                    ops.push(Op::Set(name, value));
+                }
+            }
+            for (name, value) in &rules.add_headers {
+                if let Some((name, value)) = header(name, value, vars) {
+                    ops.push(Op::Add(name, value));
+                }
+            }
+        }
+
+        Self { ops }
+    }
+
+    /// also set `headers`, ahead of the rules so those can still change them
+    pub fn with_set(mut self, headers: Vec<(HeaderName, HeaderValue)>) -> Self {
+        let ops = headers
+            .into_iter()
+            .map(|(name, value)| Op::Set(name, value));
+        self.ops.splice(0..0, ops);
+        self
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for op in &self.ops {
+            match op {
+                Op::Remove(name) => {
+                    headers.remove(name);
+                }
+                Op::Set(name, value) => {
+                    headers.insert(name.clone(), value.clone());
+                }
+                Op::Add(name, value) => {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+fn header(name: &str, value: &str, vars: &Vars) -> Option<(HeaderName, HeaderValue)> {
+    let value = vars.expand(value);
+    match (
+        HeaderName::from_bytes(name.as_bytes()),
+        HeaderValue::from_str(&value),
+    ) {
+        (Ok(name), Ok(value)) => Some((name, value)),
+        _ => {
+            debug!(header = name, value = %value, "header_rule_skipped");
+            None
+        }
+    }
+}
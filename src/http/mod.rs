@@ -1,2 +1,9 @@
+pub mod body;
+pub mod conditional;
+pub mod encoding;
+pub mod headers;
+pub mod peer;
 pub mod request;
 pub mod response;
+pub mod router;
+pub mod vhost;
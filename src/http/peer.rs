@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+
+use rustls::pki_types::CertificateDer;
+
+use crate::helpers::x509;
+
+/// what the handshake told us about the other end of a connection
+#[derive(Debug)]
+pub struct Peer {
+    pub remote: SocketAddr,
+    /// name the client asked for; virtual hosts check it against :authority
+    pub sni: Option<Arc<str>>,
+    // set once the handshake is complete: the client certificate comes last
+    handshake: OnceLock<Option<ClientCert>>,
+}
+
+/// identity of a verified client certificate
+#[derive(Debug)]
+pub struct ClientCert {
+    /// rfc 4514 style, e.g. "CN=ops,O=Example"
+    pub subject: String,
+    /// "DNS:name", "IP:addr", "URI:uri" or "email:addr"
+    pub sans: Vec<String>,
+}
+
+impl Peer {
+    /// what is known as soon as the client hello is in; call `complete`
+    /// once the handshake is done, before the requests that came after it
+    pub fn new(conn: &quinn::Connection) -> Self {
+        let sni = conn
+            .handshake_data()
+            .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+            .and_then(|data| data.server_name)
+            .map(Into::into);
+
+        Self {
+            remote: conn.remote_address(),
+            sni,
+            handshake: OnceLock::new(),
+        }
+    }
+
+    pub fn complete(&self, conn: &quinn::Connection) {
+        // rustls only hands over a chain it verified; the leaf comes first
+        let client_cert = conn
+            .peer_identity()
+            .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+            .and_then(|chain| {
+                let leaf = chain.first()?;
+                Some(ClientCert {
+                    subject: x509::subject(leaf)?,
+                    sans: x509::subject_alt_names(leaf),
+                })
+            });
+        let _ = self.handshake.set(client_cert);
+    }
+
+    /// requests read before the handshake completed came as 0-rtt early data,
+    /// which an attacker can replay
+    pub fn early(&self) -> bool {
+        self.handshake.get().is_none()
+    }
+
+    /// the verified client certificate, when the server asks for one
+    pub fn client_cert(&self) -> Option<&ClientCert> {
+        self.handshake.get()?.as_ref()
+    }
+}
+
+impl ClientCert {
+    /// whether the certificate is one of `allowed`, by subject or any san
+    pub fn matches(&self, allowed: &[String]) -> bool {
+        allowed
+            .iter()
+            .any(|entry| *entry == self.subject || self.sans.iter().any(|san| san == entry))
+    }
+}
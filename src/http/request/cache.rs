@@ -0,0 +1,121 @@
+// glue between actions and features::caching; inert when the feature isn't built
+
+use bytes::Bytes;
+use h3::server::RequestStream;
+use http::{HeaderMap, StatusCode};
+
+use super::RequestContext;
+use super::error::RequestError;
+use crate::config::Action;
+use crate::config::cache::RouteCache;
+
+/// cache settings for `action`, if it wants caching and the cache is built in.
+/// the key doesn't tell clients apart, so requests with a client certificate,
+/// which a proxy may pass on as headers, stay out of it
+pub(super) fn policy<'a>(action: &Action, ctx: &RequestContext<'a>) -> Option<&'a RouteCache> {
+    let wants_cache = match action {
+        Action::Static { cache, .. } | Action::Proxy { cache, .. } => *cache,
+        _ => false,
+    };
+
+    if cfg!(feature = "caching") && wants_cache && ctx.client_cert.is_none() {
+        ctx.route.map(|r| &r.cache)
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "caching")]
+pub(super) async fn serve(
+    ctx: &RequestContext<'_>,
+    policy: &RouteCache,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<bool, RequestError> {
+    use crate::helpers::date;
+    use crate::http::conditional::{self, Outcome};
+    use http::header::{AGE, CONTENT_LENGTH, ETAG, HeaderValue, LAST_MODIFIED};
+
+    let Some(cached) = ctx.state.cache.get(ctx.req, policy) else {
+        return Ok(false);
+    };
+
+    let mut headers = cached.headers.clone();
+    headers.insert(AGE, HeaderValue::from(cached.age().as_secs()));
+
+    // revalidation against the stored validators, the origin isn't asked
+    let etag = cached.headers.get(ETAG).and_then(|v| v.to_str().ok());
+    let modified = cached
+        .headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(date::parse);
+    // preconditions only apply to what a 2xx would have carried (rfc 9110 13.2.1)
+    let outcome = if cached.status == StatusCode::OK {
+        conditional::evaluate(ctx.req.method(), ctx.req.headers(), etag, modified)
+    } else {
+        Outcome::Proceed
+    };
+
+    match outcome {
+        Outcome::Proceed => {}
+        Outcome::NotModified => {
+            headers.remove(CONTENT_LENGTH);
+            let mut response = http::Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(())
+                .unwrap();
+            *response.headers_mut() = headers;
+            crate::http::response::send_head(stream, &ctx.head, response).await?;
+            stream.finish().await?;
+            return Ok(true);
+        }
+        Outcome::PreconditionFailed => {
+            let status = StatusCode::PRECONDITION_FAILED;
+            crate::http::response::send(
+                stream,
+                &ctx.head,
+                status,
+                "text/plain; charset=utf-8",
+                status.canonical_reason().unwrap_or("").as_bytes(),
+            )
+            .await?;
+            return Ok(true);
+        }
+    }
+
+    crate::http::response::send_with_headers(
+        stream,
+        &ctx.head,
+        cached.status,
+        headers,
+        cached.body.clone(),
+    )
+    .await?;
+    Ok(true)
+}
+
+#[cfg(not(feature = "caching"))]
+pub(super) async fn serve(
+    _: &RequestContext<'_>,
+    _: &RouteCache,
+    _: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<bool, RequestError> {
+    Ok(false)
+}
+
+#[cfg(feature = "caching")]
+pub(super) fn store(
+    ctx: &RequestContext<'_>,
+    policy: &RouteCache,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+) {
+    ctx.state
+        .cache
+        .insert(ctx.req, policy, status, headers, body);
+}
+
+#[cfg(not(feature = "caching"))]
+pub(super) fn store(_: &RequestContext<'_>, _: &RouteCache, _: StatusCode, _: HeaderMap, _: Bytes) {
+}
@@ -0,0 +1,53 @@
+// glue between responses and features::compression; nothing is compressed on
+// the fly when the feature isn't built
+
+use std::pin::Pin;
+
+use tokio::io::AsyncRead;
+
+use super::RequestContext;
+use crate::http::encoding::{self, Encoding};
+
+pub(super) type Body<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+/// whether a body of this type and size is subject to on-the-fly compression
+/// (and so varies by accept-encoding), and the encoding to use for this request
+pub(super) fn negotiate(
+    ctx: &RequestContext<'_>,
+    content_type: &str,
+    len: u64,
+) -> (bool, Option<Encoding>) {
+    let config = &ctx.server.compression;
+    let applies = cfg!(feature = "compression")
+        && config.enabled
+        && len >= config.min_size
+        && encoding::is_compressible(content_type, &config.types);
+
+    if !applies {
+        return (false, None);
+    }
+    (true, encoding::negotiate(ctx.req.headers(), &Encoding::ALL))
+}
+
+#[cfg(feature = "compression")]
+pub(super) fn body<'a, R: AsyncRead + Send + 'a>(
+    ctx: &RequestContext<'_>,
+    body: R,
+    encoding: Option<Encoding>,
+) -> Body<'a> {
+    match encoding {
+        Some(encoding) => {
+            crate::features::compression::encode(encoding, body, &ctx.server.compression)
+        }
+        None => Box::pin(body),
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+pub(super) fn body<'a, R: AsyncRead + Send + 'a>(
+    _: &RequestContext<'_>,
+    body: R,
+    _: Option<Encoding>,
+) -> Body<'a> {
+    Box::pin(body)
+}
@@ -11,6 +11,17 @@ pub enum RequestError {
     #[error(transparent)]
     Response(#[from] crate::http::response::error::ResponseError),
 
+    #[error(transparent)]
+    Body(#[from] crate::http::body::error::BodyError),
+
+    #[cfg(feature = "proxy")]
+    #[error(transparent)]
+    Proxy(#[from] crate::features::proxy::ProxyError),
+
+    #[cfg(feature = "scripting")]
+    #[error(transparent)]
+    Script(#[from] crate::features::scripting::ScriptError),
+
     #[error("invalid server configuration: {0}")]
     Config(String),
 }
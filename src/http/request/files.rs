@@ -0,0 +1,473 @@
+// Action::Static: single files, and directories with index files and listings
+
+use std::hash::{BuildHasher, RandomState};
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use h3::server::RequestStream;
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Method, StatusCode};
+use tokio::fs;
+use tokio::io::AsyncSeekExt;
+
+use super::error::RequestError;
+use super::{RequestContext, cache, compress, params};
+use crate::config::Autoindex;
+use crate::config::cache::RouteCache;
+use crate::helpers::fs::{self as static_fs, Resolved};
+use crate::helpers::range::{self, Ranges};
+use crate::helpers::{autoindex, date, mime, path as path_helpers};
+use crate::http::conditional::{self, Outcome};
+use crate::http::encoding::{self as http_encoding, Encoding};
+use crate::http::response::{self, error::ResponseError};
+
+// bytes looked at to guess a content type
+const SNIFF_LEN: usize = 8 * 1024;
+
+pub(super) async fn serve(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    path: &Path,
+    index: &[String],
+    autoindex: Autoindex,
+    content_type: Option<&str>,
+    cache_policy: Option<&RouteCache>,
+) -> Result<(), RequestError> {
+    // a path template that a parameter would take outside its directory is not found
+    let Some(root) = path_helpers::expand_file(path, params(ctx)) else {
+        return send_status(ctx, stream, StatusCode::NOT_FOUND).await;
+    };
+    let rest = ctx.matched.as_ref().map_or("", |m| m.rest.as_str());
+
+    let file = match static_fs::resolve(&root, rest).await {
+        Resolved::File(file) => file,
+        Resolved::Dir(dir) => {
+            // relative links in index pages and listings need the trailing slash
+            if !ctx.req.uri().path().ends_with('/') {
+                return redirect_to_dir(ctx, stream).await;
+            }
+
+            match find_index(&dir, index).await {
+                Some(file) => file,
+                None => return list(ctx, stream, &dir, autoindex).await,
+            }
+        }
+        Resolved::NotFound => return send_status(ctx, stream, StatusCode::NOT_FOUND).await,
+        Resolved::Forbidden => return send_status(ctx, stream, StatusCode::FORBIDDEN).await,
+        Resolved::Error => {
+            return send_status(ctx, stream, StatusCode::INTERNAL_SERVER_ERROR).await;
+        }
+    };
+
+    send_file(ctx, stream, &file, content_type, cache_policy).await
+}
+
+async fn send_file(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    path: &Path,
+    content_type: Option<&str>,
+    cache_policy: Option<&RouteCache>,
+) -> Result<(), RequestError> {
+    let (mut file, mut len, mut modified) = match static_fs::open(path).await {
+        static_fs::StaticRead::Ok {
+            file,
+            len,
+            modified,
+        } => (file, len, modified),
+        static_fs::StaticRead::NotFound => {
+            return send_status(ctx, stream, StatusCode::NOT_FOUND).await;
+        }
+        static_fs::StaticRead::Forbidden => {
+            return send_status(ctx, stream, StatusCode::FORBIDDEN).await;
+        }
+        static_fs::StaticRead::Error => {
+            return send_status(ctx, stream, StatusCode::INTERNAL_SERVER_ERROR).await;
+        }
+    };
+
+    // configured type, then the extension table, then a look at the content
+    let content_type = match content_type.or_else(|| ctx.state.mime_types.from_path(path)) {
+        Some(content_type) => HeaderValue::from_str(content_type)
+            .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream")),
+        None => {
+            let head = static_fs::sniff(&mut file, SNIFF_LEN)
+                .await
+                .map_err(ResponseError::from)?;
+            HeaderValue::from_static(mime::from_bytes(&head))
+        }
+    };
+
+    // representation: a precompressed sibling, compressed on the fly, or as is
+    let siblings = precompressed(ctx, path).await;
+    let (vary, encoding, on_the_fly) = if siblings.is_empty() {
+        let content_type = content_type.to_str().unwrap_or_default();
+        let (vary, encoding) = compress::negotiate(ctx, content_type, len);
+        (vary, encoding, encoding)
+    } else {
+        let mut encoding = None;
+        if let Some(chosen) = http_encoding::negotiate(ctx.req.headers(), &siblings)
+            && let static_fs::StaticRead::Ok {
+                file: sibling,
+                len: sibling_len,
+                modified: sibling_modified,
+            } = static_fs::open(&sibling_path(path, chosen)).await
+        {
+            (file, len, modified) = (sibling, sibling_len, sibling_modified);
+            encoding = Some(chosen);
+        }
+        (true, encoding, None)
+    };
+
+    let validators = ctx.route.map(|r| r.validators.clone()).unwrap_or_default();
+    let mut etag = conditional::etag(validators.etag, modified, len);
+    if let (Some(tag), Some(encoding)) = (&etag, on_the_fly) {
+        etag = Some(conditional::weaken(tag, encoding.token()));
+    }
+    let modified = modified.filter(|_| validators.last_modified);
+
+    let mut headers = HeaderMap::new();
+    // ranges of a body that is compressed while it is sent can't be served
+    if on_the_fly.is_none() {
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+    if vary {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    if let Some(encoding) = encoding {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.token()),
+        );
+    }
+    if let Some(etag) = etag.as_deref().and_then(|e| HeaderValue::from_str(e).ok()) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(modified) = modified
+        && let Ok(value) = HeaderValue::from_str(&date::format(modified))
+    {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+
+    match conditional::evaluate(
+        ctx.req.method(),
+        ctx.req.headers(),
+        etag.as_deref(),
+        modified,
+    ) {
+        Outcome::Proceed => {}
+        Outcome::NotModified => {
+            let mut response = http::Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(())
+                .unwrap();
+            *response.headers_mut() = headers;
+            response::send_head(stream, &ctx.head, response).await?;
+            stream.finish().await?;
+            return Ok(());
+        }
+        Outcome::PreconditionFailed => {
+            return send_status(ctx, stream, StatusCode::PRECONDITION_FAILED).await;
+        }
+    }
+
+    let ranges = match ctx.req.headers().get(header::RANGE) {
+        Some(value)
+            if *ctx.req.method() == Method::GET
+                && on_the_fly.is_none()
+                && conditional::if_range_holds(ctx.req.headers(), etag.as_deref(), modified) =>
+        {
+            value
+                .to_str()
+                .map_or(Ranges::Full, |value| range::parse(value, len))
+        }
+        _ => Ranges::Full,
+    };
+
+    match ranges {
+        Ranges::Full => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+            // the compressed length is only known once it has been sent
+            let body_len = on_the_fly.is_none().then_some(len);
+            if let Some(len) = body_len {
+                headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+            }
+
+            // only files that can fit in the cache are copied on the way out
+            let mut capture = cache_policy
+                .filter(|p| on_the_fly.is_some() || len <= p.max_entry_size)
+                .map(|p| response::Capture::new(p.max_entry_size));
+
+            response::send_reader(
+                stream,
+                &ctx.head,
+                StatusCode::OK,
+                headers,
+                compress::body(ctx, &mut file, on_the_fly),
+                body_len,
+                capture.as_mut(),
+            )
+            .await?;
+
+            if let (Some(policy), Some((status, headers, body))) =
+                (cache_policy, capture.and_then(response::Capture::finish))
+            {
+                cache::store(ctx, policy, status, headers, body);
+            }
+            Ok(())
+        }
+
+        Ranges::Unsatisfiable => {
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{len}")) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+
+            response::send_with_headers(
+                stream,
+                &ctx.head,
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                headers,
+                Bytes::from_static(b"Range Not Satisfiable"),
+            )
+            .await
+            .map_err(Into::into)
+        }
+
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            headers.insert(header::CONTENT_TYPE, content_type);
+            headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(range.end - range.start),
+            );
+            if let Ok(value) = HeaderValue::from_str(&range::content_range(range, len)) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+
+            file.seek(SeekFrom::Start(range.start))
+                .await
+                .map_err(ResponseError::from)?;
+
+            response::send_reader(
+                stream,
+                &ctx.head,
+                StatusCode::PARTIAL_CONTENT,
+                headers,
+                &mut file,
+                Some(range.end - range.start),
+                None,
+            )
+            .await
+            .map_err(Into::into)
+        }
+
+        Ranges::Partial(ranges) => {
+            send_multipart(ctx, stream, headers, file, len, &content_type, &ranges).await
+        }
+    }
+}
+
+// multipart/byteranges (rfc 9110 14.6), each part streamed from the file in turn
+async fn send_multipart(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    mut headers: HeaderMap,
+    mut file: fs::File,
+    len: u64,
+    content_type: &HeaderValue,
+    ranges: &[Range<u64>],
+) -> Result<(), RequestError> {
+    let boundary = format!("{:016x}", RandomState::new().hash_one(len));
+    let content_type = content_type.to_str().unwrap_or("application/octet-stream");
+
+    let part_heads: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{boundary}\r\ncontent-type: {content_type}\r\ncontent-range: {}\r\n\r\n",
+                range::content_range(range, len)
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{boundary}--\r\n");
+
+    let body_len = part_heads.iter().map(|h| h.len() as u64).sum::<u64>()
+        + ranges.iter().map(|r| r.end - r.start).sum::<u64>()
+        + closing.len() as u64;
+
+    if let Ok(value) = HeaderValue::from_str(&format!("multipart/byteranges; boundary={boundary}"))
+    {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body_len));
+
+    let mut response = http::Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .body(())
+        .unwrap();
+    *response.headers_mut() = headers;
+    response::send_head(stream, &ctx.head, response).await?;
+
+    for (range, part_head) in ranges.iter().zip(part_heads) {
+        stream.send_data(Bytes::from(part_head)).await?;
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(ResponseError::from)?;
+        response::send_body(stream, &mut file, Some(range.end - range.start), None).await?;
+    }
+
+    stream.send_data(Bytes::from(closing)).await?;
+    stream.finish().await?;
+    Ok(())
+}
+
+// precompressed siblings of `path` that exist, in order of preference.
+// symlinks are not followed, so a sibling can't lead out of the served tree
+async fn precompressed(ctx: &RequestContext<'_>, path: &Path) -> Vec<Encoding> {
+    let mut found = Vec::new();
+    if !ctx.server.compression.precompressed {
+        return found;
+    }
+
+    for encoding in Encoding::ALL {
+        if fs::symlink_metadata(sibling_path(path, encoding))
+            .await
+            .is_ok_and(|m| m.is_file())
+        {
+            found.push(encoding);
+        }
+    }
+    found
+}
+
+fn sibling_path(path: &Path, encoding: Encoding) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(encoding.extension());
+    PathBuf::from(name)
+}
+
+async fn find_index(dir: &Path, index: &[String]) -> Option<PathBuf> {
+    for name in index {
+        // index names come from the config, but stay inside the directory anyway
+        if !path_helpers::is_safe_relative(name) {
+            continue;
+        }
+
+        let candidate = dir.join(name);
+        if fs::metadata(&candidate).await.is_ok_and(|m| m.is_file()) {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+async fn list(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    dir: &Path,
+    autoindex: Autoindex,
+) -> Result<(), RequestError> {
+    if autoindex == Autoindex::Off {
+        return send_status(ctx, stream, StatusCode::FORBIDDEN).await;
+    }
+
+    let entries = match autoindex::read(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!(dir = %dir.display(), error = %e, "autoindex_failed");
+            return send_status(ctx, stream, StatusCode::INTERNAL_SERVER_ERROR).await;
+        }
+    };
+
+    let (content_type, body) = match autoindex {
+        Autoindex::Json => ("application/json", autoindex::json(&entries)),
+        _ => (
+            "text/html; charset=utf-8",
+            autoindex::html(ctx.req.uri().path(), &entries),
+        ),
+    };
+
+    response::send(
+        stream,
+        &ctx.head,
+        StatusCode::OK,
+        content_type,
+        body.as_bytes(),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+async fn redirect_to_dir(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<(), RequestError> {
+    let location = dir_location(ctx.req.uri());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    if let Ok(location) = HeaderValue::from_str(&location) {
+        headers.insert(header::LOCATION, location);
+    }
+
+    response::send_with_headers(
+        stream,
+        &ctx.head,
+        StatusCode::MOVED_PERMANENTLY,
+        headers,
+        Bytes::from_static(b"Moved Permanently"),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+// the directory with its trailing slash. "//evil.example" stays a path here
+fn dir_location(uri: &http::Uri) -> String {
+    let path = path_helpers::local_path(uri.path());
+    match uri.query() {
+        Some(query) => format!("{path}/?{query}"),
+        None => format!("{path}/"),
+    }
+}
+
+async fn send_status(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    status: StatusCode,
+) -> Result<(), RequestError> {
+    let reason = status.canonical_reason().unwrap_or("Error");
+    response::send(
+        stream,
+        &ctx.head,
+        status,
+        "text/plain; charset=utf-8",
+        reason.as_bytes(),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_location_stays_on_this_host() {
+        let location = |uri: &str| dir_location(&uri.parse().unwrap());
+
+        assert_eq!(location("/docs"), "/docs/");
+        assert_eq!(location("/docs?a=1"), "/docs/?a=1");
+        assert_eq!(location("//evil.example"), "/evil.example/");
+        assert_eq!(location("//evil.example?a=1"), "/evil.example/?a=1");
+    }
+}
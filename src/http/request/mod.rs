@@ -1,108 +1,453 @@
+mod cache;
+mod compress;
 mod error;
+mod files;
+mod ratelimit;
 
-use crate::config::{Action, AppConfig};
-use crate::helpers::{fs as static_fs, mime};
+use crate::config::{Action, AppConfig, RouteConfig, Server, StandardResponses};
+use crate::helpers::id;
+use crate::http::body::{Body, error::BodyError};
+use crate::http::headers::{HeaderRewrite, Vars};
+use crate::http::peer::{ClientCert, Peer};
 use crate::http::request::error::RequestError;
 use crate::http::response;
+use crate::http::router::{self, RouteMatch};
+use crate::http::vhost::{self, Host};
+use crate::state::State;
 use bytes::Bytes;
 use h3::server::RequestStream;
 use http::StatusCode;
+use std::iter;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+// what an action may need to know about the request it answers
+struct RequestContext<'a> {
+    req: &'a http::Request<()>,
+    state: &'a State,
+    remote: SocketAddr,
+    // verified during the handshake; None without mutual tls
+    client_cert: Option<&'a ClientCert>,
+    // sent as 0-rtt early data, before the handshake was done
+    early: bool,
+    server: &'a Server,
+    // of the virtual host, or the server's when the request is misdirected
+    standard: &'a StandardResponses,
+    misdirected: bool,
+    rate_limited: bool,
+    route: Option<&'a RouteConfig>,
+    matched: Option<RouteMatch<'a>>,
+    // None when bodies of any size are accepted
+    body_limit: Option<u64>,
+    #[cfg_attr(not(feature = "proxy"), allow(dead_code))]
+    upstream_headers: HeaderRewrite,
+    vars: Vars,
+    head: response::Head,
+}
+
 pub async fn handle_request(
     req: http::Request<()>,
     mut stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
     config: Arc<AppConfig>,
+    state: Arc<State>,
     server_name: Arc<String>,
+    peer: Arc<Peer>,
 ) -> Result<(), RequestError> {
     let server = config
         .servers
         .get(&*server_name)
         .ok_or_else(|| RequestError::Config("missing server config".into()))?;
 
+    // sni only stands in for requests without an authority
+    let sni = peer.sni.as_deref();
+    let authority = req.uri().authority().map(|a| a.as_str());
+    let host =
+        vhost::select(server, authority.or(sni)).filter(|host| !misdirected(server, host, sni));
+
     let path = req.uri().path();
-    let method = req.method().as_str();
 
-    let route = match server.routes.get(path) {
-        Some(route) => route,
-        None => {
-            return execute_action(&server.standard.not_found, &mut stream).await;
-        }
+    let found = host
+        .as_ref()
+        .and_then(|h| state.routers.get(&*server_name)?.get(h.name)?.find(path));
+    let route = found
+        .as_ref()
+        .and_then(|f| host.as_ref()?.routes.get(f.key));
+
+    // the server's limit covers every request, a route's only its own
+    let route_scope = |key: &str| {
+        let host = host.as_ref().and_then(|h| h.name).unwrap_or_default();
+        format!("{server_name}/{host}/{key}")
     };
+    let limits = server
+        .rate_limit
+        .as_ref()
+        .map(|limit| (server_name.to_string(), limit))
+        .into_iter()
+        .chain(
+            found
+                .as_ref()
+                .zip(route.and_then(|r| r.rate_limit.as_ref()))
+                .map(|(f, limit)| (route_scope(f.key), limit)),
+        );
+    let verdict = ratelimit::check(&state, &req, &peer, limits);
 
-    let action = match route.methods.get(method) {
-        Some(action) => action,
-        None => {
-            return execute_action(&server.standard.method_not_allowed, &mut stream).await;
-        }
+    let request_id = id::request_id();
+    let vars = Vars::new(&req, &peer, &server_name, &request_id);
+    let rewrite = HeaderRewrite::new(
+        iter::once(&server.headers).chain(route.map(|r| &r.headers)),
+        &vars,
+    )
+    .with_set(verdict.headers);
+
+    let limit = route
+        .and_then(|r| r.max_body_size)
+        .unwrap_or(server.max_body_size);
+
+    let early = peer.early();
+    let early_limit = server
+        .tls
+        .as_ref()
+        .map_or(0, |tls| u64::from(tls.max_early_data_size));
+    let limit = match (early, limit) {
+        (false, _) => limit,
+        (true, 0) => early_limit,
+        (true, limit) => limit.min(early_limit),
+    };
+
+    let ctx = RequestContext {
+        req: &req,
+        state: &state,
+        remote: peer.remote,
+        client_cert: peer.client_cert(),
+        early,
+        server,
+        standard: host.as_ref().map_or(&server.standard, |h| h.standard),
+        misdirected: host.is_none(),
+        rate_limited: verdict.exceeded,
+        route,
+        matched: found.filter(|_| route.is_some()),
+        body_limit: (limit > 0).then_some(limit),
+        upstream_headers: HeaderRewrite::new(
+            iter::once(&server.upstream_headers).chain(route.map(|r| &r.upstream_headers)),
+            &vars,
+        ),
+        vars,
+        head: response::Head::new(rewrite),
+    };
+
+    dispatch(&ctx, &mut stream).await
+}
+
+async fn dispatch(
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<(), RequestError> {
+    let standard = ctx.standard;
+
+    if ctx.misdirected {
+        tracing::debug!(remote = %ctx.remote, authority = ?ctx.req.uri().authority(), "request_misdirected");
+        return execute_action(&standard.misdirected_request, ctx, stream).await;
+    }
+
+    if ctx.rate_limited {
+        return execute_action(&standard.too_many_requests, ctx, stream).await;
+    }
+
+    let Some(route) = ctx.route else {
+        return execute_action(&standard.not_found, ctx, stream).await;
     };
 
+    if ctx.early && !replay_safe(route, ctx.req.method()) {
+        tracing::debug!(remote = %ctx.remote, method = %ctx.req.method(), "request_too_early");
+        return execute_action(&standard.too_early, ctx, stream).await;
+    }
+
+    // the client certificate is only known once the handshake is done
+    if ctx.early && !route.require_client.is_empty() {
+        return execute_action(&standard.too_early, ctx, stream).await;
+    }
+
+    if !route.require_client.is_empty()
+        && !ctx
+            .client_cert
+            .is_some_and(|cert| cert.matches(&route.require_client))
+    {
+        tracing::debug!(
+            remote = %ctx.remote,
+            subject = ?ctx.client_cert.map(|c| &c.subject),
+            "client_cert_rejected"
+        );
+        return execute_action(&standard.forbidden, ctx, stream).await;
+    }
+
+    let Some(action) = route.methods.get(ctx.req.method().as_str()) else {
+        return execute_action(&standard.method_not_allowed, ctx, stream).await;
+    };
+
+    // bodies announced too large are refused before anything reads them
+    if let Err(e) = Body::new(ctx.req, ctx.body_limit) {
+        return reject_body(e, ctx, stream).await;
+    }
+
     // execute resolved action
-    if execute_action(action, &mut stream).await.is_err() {
-        return execute_action(&server.standard.internal_error, &mut stream).await;
+    match execute_action(action, ctx, stream).await {
+        Ok(()) => Ok(()),
+        // too late for another response, cut this one short
+        Err(e) if ctx.head.sent() => {
+            stream.stop_stream(h3::error::Code::H3_INTERNAL_ERROR);
+            Err(e)
+        }
+        Err(RequestError::Body(e)) => reject_body(e, ctx, stream).await,
+        Err(_) => execute_action(&standard.internal_error, ctx, stream).await,
     }
+}
+
+async fn reject_body(
+    e: BodyError,
+    ctx: &RequestContext<'_>,
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<(), RequestError> {
+    if let BodyError::Stream(e) = e {
+        return Err(e.into());
+    }
+
+    tracing::debug!(remote = %ctx.remote, error = %e, "request_body_rejected");
+
+    // answered early: the rest of the body isn't wanted
+    stream.stop_sending(h3::error::Code::H3_NO_ERROR);
 
-    Ok(())
+    match e {
+        // over the early data cap: fine to send again once the handshake is done
+        BodyError::TooLarge { .. } if ctx.early => {
+            execute_action(&ctx.standard.too_early, ctx, stream).await
+        }
+        BodyError::TooLarge { .. } => {
+            execute_action(&ctx.standard.payload_too_large, ctx, stream).await
+        }
+        _ => response::send(
+            stream,
+            &ctx.head,
+            StatusCode::BAD_REQUEST,
+            "text/plain; charset=utf-8",
+            b"Bad Request",
+        )
+        .await
+        .map_err(Into::into),
+    }
 }
 
 async fn execute_action(
     action: &Action,
+    ctx: &RequestContext<'_>,
     stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
 ) -> Result<(), RequestError> {
+    let cache_policy = cache::policy(action, ctx);
+    if let Some(policy) = cache_policy
+        && cache::serve(ctx, policy, stream).await?
+    {
+        return Ok(());
+    }
+
     match action {
         Action::Response {
             body,
             content_type,
             status,
-        } => response::send(
-            stream,
-            StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            content_type,
-            body.as_bytes(),
-        )
-        .await
-        .map_err(Into::into),
+        } => {
+            let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
 
-        Action::Static { path, .. } => match static_fs::read(path).await {
-            static_fs::StaticRead::Ok(data) => {
-                let ct = mime::from_bytes(&data);
-                response::send(stream, StatusCode::OK, ct, &data)
+            let (vary, encoding) = compress::negotiate(ctx, content_type, body.len() as u64);
+            if !vary {
+                return response::send(stream, &ctx.head, status, content_type, body.as_bytes())
                     .await
-                    .map_err(Into::into)
+                    .map_err(Into::into);
             }
 
-            static_fs::StaticRead::NotFound => response::send(
+            let mut headers = http::HeaderMap::new();
+            if let Ok(value) = http::HeaderValue::from_str(content_type) {
+                headers.insert(http::header::CONTENT_TYPE, value);
+            }
+            headers.insert(
+                http::header::VARY,
+                http::HeaderValue::from_static("accept-encoding"),
+            );
+            if let Some(encoding) = encoding {
+                headers.insert(
+                    http::header::CONTENT_ENCODING,
+                    http::HeaderValue::from_static(encoding.token()),
+                );
+            }
+
+            response::send_reader(
                 stream,
-                StatusCode::NOT_FOUND,
-                "text/plain; charset=utf-8",
-                b"Not Found",
+                &ctx.head,
+                status,
+                headers,
+                compress::body(ctx, body.as_bytes(), encoding),
+                encoding.is_none().then_some(body.len() as u64),
+                None,
             )
             .await
-            .map_err(Into::into),
+            .map_err(Into::into)
+        }
 
-            static_fs::StaticRead::Forbidden => response::send(
+        Action::Static {
+            path,
+            index,
+            autoindex,
+            content_type,
+            ..
+        } => {
+            files::serve(
+                ctx,
                 stream,
-                StatusCode::FORBIDDEN,
-                "text/plain; charset=utf-8",
-                b"Forbidden",
+                path,
+                index,
+                *autoindex,
+                content_type.as_deref(),
+                cache_policy,
             )
             .await
-            .map_err(Into::into),
+        }
+
+        Action::Redirect { to, status } => {
+            // checked at config load
+            let Some(status) = StatusCode::from_u16(*status)
+                .ok()
+                .filter(|s| router::is_redirect_status(s.as_u16()))
+            else {
+                return Err(RequestError::Config(format!(
+                    "invalid redirect status {status}"
+                )));
+            };
 
-            static_fs::StaticRead::Error => response::send(
+            // variables first, so a '$' in a path parameter stays as it is
+            let mut location = router::expand(&ctx.vars.expand(to), params(ctx));
+
+            // a request path like "//evil.example" must not turn a local target
+            // into one on another host
+            if !to.starts_with("//") {
+                location = crate::helpers::path::local_path(&location);
+            }
+
+            let mut headers = http::HeaderMap::new();
+            headers.insert(
+                http::header::CONTENT_TYPE,
+                http::HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            headers.insert(
+                http::header::LOCATION,
+                http::HeaderValue::from_str(&location).map_err(|_| {
+                    RequestError::Config(format!("invalid redirect target '{location}'"))
+                })?,
+            );
+
+            response::send_with_headers(
                 stream,
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "text/plain; charset=utf-8",
-                b"Internal Server Error",
+                &ctx.head,
+                status,
+                headers,
+                Bytes::from_static(status.canonical_reason().unwrap_or("").as_bytes()),
             )
             .await
-            .map_err(Into::into),
-        },
+            .map_err(Into::into)
+        }
+
+        #[cfg(feature = "proxy")]
+        Action::Proxy {
+            upstream,
+            rewrite,
+            timeout_secs,
+            ..
+        } => {
+            use crate::features::proxy::{self, ProxyError};
+
+            let mut capture = cache_policy.map(|p| response::Capture::new(p.max_entry_size));
+
+            let path = match rewrite.as_deref() {
+                Some(template) => {
+                    let Some(path) = crate::helpers::path::expand_uri(template, params(ctx)) else {
+                        tracing::debug!(remote = %ctx.remote, path = %ctx.req.uri().path(), "proxy_rewrite_rejected");
+                        return response::send(
+                            stream,
+                            &ctx.head,
+                            StatusCode::BAD_REQUEST,
+                            "text/plain; charset=utf-8",
+                            b"Bad Request",
+                        )
+                        .await
+                        .map_err(Into::into);
+                    };
+                    Some(if path.starts_with('/') {
+                        path
+                    } else {
+                        format!("/{path}")
+                    })
+                }
+                None => None,
+            };
+
+            let mut body = Body::new(ctx.req, ctx.body_limit)?;
+            let result = proxy::forward(
+                ctx.req,
+                stream,
+                &ctx.head,
+                &mut body,
+                upstream,
+                path.as_deref(),
+                &ctx.state.upstreams,
+                ctx.remote,
+                ctx.early,
+                std::time::Duration::from_secs(*timeout_secs),
+                &ctx.upstream_headers,
+                capture.as_mut(),
+            )
+            .await;
 
+            match result {
+                Ok(()) => {
+                    if let (Some(policy), Some((status, headers, body))) =
+                        (cache_policy, capture.and_then(response::Capture::finish))
+                    {
+                        cache::store(ctx, policy, status, headers, body);
+                    }
+                    Ok(())
+                }
+                Err(e @ (ProxyError::Upstream(_) | ProxyError::NoBackend(_))) => {
+                    tracing::warn!(upstream = %upstream, error = %e, "proxy_upstream_failed");
+                    response::send(
+                        stream,
+                        &ctx.head,
+                        StatusCode::BAD_GATEWAY,
+                        "text/plain; charset=utf-8",
+                        b"Bad Gateway",
+                    )
+                    .await
+                    .map_err(Into::into)
+                }
+                Err(e @ ProxyError::Timeout(_)) => {
+                    tracing::warn!(upstream = %upstream, error = %e, "proxy_upstream_failed");
+                    response::send(
+                        stream,
+                        &ctx.head,
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "text/plain; charset=utf-8",
+                        b"Gateway Timeout",
+                    )
+                    .await
+                    .map_err(Into::into)
+                }
+                Err(ProxyError::Body(e)) => Err(e.into()),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        #[cfg(not(feature = "proxy"))]
         Action::Proxy { .. } => {
-            // not implemented.
+            // not built.
             response::send(
                 stream,
+                &ctx.head,
                 StatusCode::NOT_IMPLEMENTED,
                 "text/plain; charset=utf-8",
                 b"Proxy not implemented",
@@ -111,10 +456,69 @@ async fn execute_action(
             .map_err(Into::into)
         }
 
+        #[cfg(feature = "scripting")]
+        Action::Script {
+            script,
+            interpreter,
+            timeout_secs,
+        } => {
+            use crate::features::scripting::{self, CgiRequest, ScriptError};
+
+            // whatever a prefix or wildcard route left over is the script's path info
+            let path = ctx.req.uri().path();
+            let rest = ctx.matched.as_ref().map_or("", |m| m.rest.as_str());
+            let path_info = if rest.is_empty() {
+                String::new()
+            } else {
+                format!("/{rest}")
+            };
+            let script_name = path
+                .strip_suffix(path_info.as_str())
+                .unwrap_or(path)
+                .trim_end_matches('/');
+
+            let cgi = CgiRequest {
+                req: ctx.req,
+                remote: ctx.remote,
+                script_name,
+                path_info: &path_info,
+                server_port: ctx.server.port,
+            };
+
+            let mut body = Body::new(ctx.req, ctx.body_limit)?;
+            let result = scripting::run(
+                &ctx.state.scripts,
+                &cgi,
+                stream,
+                &ctx.head,
+                &mut body,
+                script,
+                interpreter,
+                std::time::Duration::from_secs(*timeout_secs),
+            )
+            .await;
+
+            match result {
+                Err(ScriptError::Timeout) => response::send(
+                    stream,
+                    &ctx.head,
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "text/plain; charset=utf-8",
+                    b"Gateway Timeout",
+                )
+                .await
+                .map_err(Into::into),
+                Err(ScriptError::Body(e)) => Err(e.into()),
+                result => result.map_err(Into::into),
+            }
+        }
+
+        #[cfg(not(feature = "scripting"))]
         Action::Script { .. } => {
-            // not implemented.
+            // not built.
             response::send(
                 stream,
+                &ctx.head,
                 StatusCode::NOT_IMPLEMENTED,
                 "text/plain; charset=utf-8",
                 b"Script execution not implemented",
@@ -124,3 +528,25 @@ async fn execute_action(
         }
     }
 }
+
+// early data can be replayed, so only what the route allows is served from it
+fn replay_safe(route: &RouteConfig, method: &http::Method) -> bool {
+    route
+        .replay_safe
+        .unwrap_or(matches!(*method, http::Method::GET | http::Method::HEAD))
+}
+
+// the certificate of a connection was chosen by its sni, so it may only be
+// reused for hosts presenting the same one (rfc 9110 15.5.20)
+fn misdirected(server: &Server, host: &Host<'_>, sni: Option<&str>) -> bool {
+    let Some(sni) = sni else {
+        return false;
+    };
+
+    let presented = vhost::select(server, Some(sni)).map_or(server.tls.as_ref(), |h| h.tls);
+    presented != host.tls
+}
+
+fn params<'c>(ctx: &'c RequestContext<'_>) -> &'c [(String, String)] {
+    ctx.matched.as_ref().map_or(&[], |m| &m.params)
+}
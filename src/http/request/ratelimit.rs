@@ -0,0 +1,101 @@
+// glue between requests and features::ratelimit; inert when the feature isn't built
+
+use http::header::{HeaderName, HeaderValue};
+
+use crate::config::RateLimit;
+use crate::http::peer::Peer;
+use crate::state::State;
+
+/// what the rate limits of a request decided
+#[derive(Default)]
+pub(super) struct Verdict {
+    pub exceeded: bool,
+    /// RateLimit-* of the tightest limit, and Retry-After when exceeded
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// take the request from each of `limits`, a scope naming whose limit it is
+/// and the limit itself. every limit counts it, even once one is exceeded.
+#[cfg(feature = "ratelimit")]
+pub(super) fn check<'l>(
+    state: &State,
+    req: &http::Request<()>,
+    peer: &Peer,
+    limits: impl IntoIterator<Item = (String, &'l RateLimit)>,
+) -> Verdict {
+    use crate::config::ratelimit::RateLimitKey;
+    use crate::features::ratelimit::Outcome;
+    use crate::helpers::net;
+
+    let ip = peer.remote.ip().to_canonical();
+    let mut tightest: Option<Outcome> = None;
+    let mut exceeded = false;
+
+    for (scope, limit) in limits {
+        let key = match &limit.key {
+            RateLimitKey::Ip => ip.to_string(),
+            RateLimitKey::Prefix => net::prefix(ip, 32, 64).to_string(),
+            RateLimitKey::Header(name) => req
+                .headers()
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map_or_else(|| ip.to_string(), |v| format!("h:{v}")),
+            RateLimitKey::Route => String::new(),
+        };
+
+        let outcome = state.rate_limiter.check(&scope, &key, limit);
+        exceeded |= !outcome.allowed;
+
+        let tighter = match &tightest {
+            None => true,
+            Some(t) if t.allowed != outcome.allowed => !outcome.allowed,
+            Some(t) => outcome.remaining < t.remaining,
+        };
+        if tighter {
+            tightest = Some(outcome);
+        }
+    }
+
+    let Some(outcome) = tightest else {
+        return Verdict::default();
+    };
+
+    // draft-ietf-httpapi-ratelimit-headers
+    let mut values = vec![
+        (
+            "ratelimit-policy",
+            format!("{};w={}", outcome.limit, outcome.period_secs),
+        ),
+        ("ratelimit-limit", outcome.limit.to_string()),
+        ("ratelimit-remaining", outcome.remaining.to_string()),
+        ("ratelimit-reset", outcome.reset.as_secs().to_string()),
+    ];
+    if exceeded {
+        values.push((
+            "retry-after",
+            outcome.retry_after.as_secs().max(1).to_string(),
+        ));
+    }
+
+    let headers = values
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_static(name),
+                HeaderValue::from_str(&value).ok()?,
+            ))
+        })
+        .collect();
+
+    Verdict { exceeded, headers }
+}
+
+#[cfg(not(feature = "ratelimit"))]
+pub(super) fn check<'l>(
+    _state: &State,
+    _req: &http::Request<()>,
+    _peer: &Peer,
+    _limits: impl IntoIterator<Item = (String, &'l RateLimit)>,
+) -> Verdict {
+    Verdict::default()
+}
@@ -4,4 +4,7 @@ use thiserror::Error;
 pub enum ResponseError {
     #[error(transparent)]
     Stream(#[from] h3::error::StreamError),
+
+    #[error("failed to read response body: {0}")]
+    Io(#[from] std::io::Error),
 }
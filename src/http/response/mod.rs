@@ -1,14 +1,55 @@
 pub mod error;
 // mod
 
-use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bytes::{Bytes, BytesMut};
 use h3::server::RequestStream;
-use http::{Response, StatusCode};
+use http::{HeaderMap, Response, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
+use crate::http::headers::HeaderRewrite;
 use crate::http::response::error::ResponseError;
 
+// bodies read from disk are sent in chunks of at most this size
+const READ_CHUNK: usize = 64 * 1024;
+
+/// the response to one request: its header rules, and whether its head went out
+pub struct Head {
+    rewrite: HeaderRewrite,
+    sent: AtomicBool,
+}
+
+impl Head {
+    pub fn new(rewrite: HeaderRewrite) -> Self {
+        Self {
+            rewrite,
+            sent: AtomicBool::new(false),
+        }
+    }
+
+    /// whether the response head was sent; after that, a failure can only cut
+    /// the response short, there is no answering with an error page anymore
+    pub fn sent(&self) -> bool {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+/// send a response head, after the request's header rules had their say.
+/// everything answering a request sends its head through here.
+pub async fn send_head(
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    mut response: Response<()>,
+) -> Result<(), h3::error::StreamError> {
+    head.rewrite.apply(response.headers_mut());
+    head.sent.store(true, Ordering::Relaxed);
+    stream.send_response(response).await
+}
+
 pub async fn send(
     stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
     status: StatusCode,
     content_type: &str,
     body: &[u8],
@@ -19,9 +60,138 @@ pub async fn send(
         .body(())
         .unwrap();
 
-    stream.send_response(response).await?;
+    send_head(stream, head, response).await?;
     stream.send_data(Bytes::copy_from_slice(body)).await?;
     stream.finish().await?;
 
     Ok(())
 }
+
+// like `send`, for responses that carry a full header map
+pub async fn send_with_headers(
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), ResponseError> {
+    let mut response = Response::builder().status(status).body(()).unwrap();
+    *response.headers_mut() = headers;
+
+    send_head(stream, head, response).await?;
+    stream.send_data(body).await?;
+    stream.finish().await?;
+
+    Ok(())
+}
+
+/// stream `len` bytes of `body`, or all of it when `len` is `None`, after the
+/// response head. `send_data` only returns once quic flow control took the chunk,
+/// so one chunk at a time is in memory. a body that ends before `len` resets the
+/// stream instead of finishing it short.
+pub async fn send_reader<R: AsyncRead + Unpin>(
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    head: &Head,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: R,
+    len: Option<u64>,
+    mut capture: Option<&mut Capture>,
+) -> Result<(), ResponseError> {
+    let mut response = Response::builder().status(status).body(()).unwrap();
+    *response.headers_mut() = headers;
+
+    if let Some(capture) = capture.as_mut() {
+        capture.head(status, response.headers());
+    }
+    send_head(stream, head, response).await?;
+
+    send_body(stream, body, len, capture).await?;
+    stream.finish().await?;
+    Ok(())
+}
+
+/// the body half of `send_reader`, for responses sent in several pieces
+pub async fn send_body<R: AsyncRead + Unpin>(
+    stream: &mut RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    body: R,
+    len: Option<u64>,
+    mut capture: Option<&mut Capture>,
+) -> Result<(), ResponseError> {
+    // a file growing meanwhile doesn't make the body longer than announced
+    let mut body = body.take(len.unwrap_or(u64::MAX));
+    let mut sent = 0u64;
+    while len.is_none_or(|len| sent < len) {
+        let mut chunk = BytesMut::with_capacity(READ_CHUNK);
+        let read = match body.read_buf(&mut chunk).await {
+            Ok(0) if len.is_none() => break,
+            Ok(0) => Err(std::io::ErrorKind::UnexpectedEof.into()),
+            result => result,
+        };
+
+        let read = match read {
+            Ok(read) => read,
+            Err(e) => {
+                stream.stop_stream(h3::error::Code::H3_INTERNAL_ERROR);
+                return Err(e.into());
+            }
+        };
+
+        sent += read as u64;
+        let chunk = chunk.freeze();
+        if let Some(capture) = capture.as_mut() {
+            capture.data(&chunk);
+        }
+        stream.send_data(chunk).await?;
+    }
+
+    Ok(())
+}
+
+/// copy of a response taken while it is streamed to the client, e.g. for the cache.
+/// gives up, and frees what it holds, once the body grows past `limit` bytes.
+pub struct Capture {
+    limit: u64,
+    head: Option<(StatusCode, HeaderMap)>,
+    body: BytesMut,
+    overflowed: bool,
+}
+
+impl Capture {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            head: None,
+            body: BytesMut::new(),
+            overflowed: false,
+        }
+    }
+
+    pub fn head(&mut self, status: StatusCode, headers: &HeaderMap) {
+        self.head = Some((status, headers.clone()));
+    }
+
+    pub fn data(&mut self, data: &[u8]) {
+        if self.overflowed {
+            return;
+        }
+
+        if (self.body.len() + data.len()) as u64 > self.limit {
+            self.overflowed = true;
+            self.body = BytesMut::new();
+            return;
+        }
+
+        self.body.extend_from_slice(data);
+    }
+
+    /// the whole response, unless it got too big
+    pub fn finish(self) -> Option<(StatusCode, HeaderMap, Bytes)> {
+        if self.overflowed {
+            return None;
+        }
+
+        let (status, headers) = self.head?;
+        Some((status, headers, self.body.freeze()))
+    }
+}
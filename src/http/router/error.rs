@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RouterError {
+    #[error("invalid route pattern '{route}': {reason}")]
+    InvalidPattern { route: String, reason: String },
+
+    #[error("invalid route regex '{route}': {source}")]
+    InvalidRegex {
+        route: String,
+        source: Box<regex::Error>,
+    },
+
+    #[error("invalid redirect status {status} in '{route}', expected 301, 302, 303, 307 or 308")]
+    InvalidRedirectStatus { route: String, status: u16 },
+
+    #[error("default host '{host}' of server '{server}' is not one of its hosts")]
+    UnknownDefaultHost { server: String, host: String },
+}
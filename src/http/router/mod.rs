@@ -0,0 +1,426 @@
+pub mod error;
+
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::config::{Action, RouteConfig};
+use error::RouterError;
+
+/// captured route parameters, in the order they appear, still percent-encoded
+pub type Params = Vec<(String, String)>;
+
+// route keys, by kind:
+//   /about            exact, or a segment-wise prefix with `prefix = true`
+//   /users/{id}       pattern; `{name}` matches one segment
+//   /files/*path      pattern; a trailing `*name` matches the rest of the path
+//   ~^/img/(?P<n>.+)$ regex; named groups become parameters
+// precedence: exact, then the longest prefix, then patterns (most literal
+// segments first), then regexes in key order.
+pub struct Router {
+    exact: HashSet<String>,
+    prefixes: Vec<String>,
+    patterns: Vec<Pattern>,
+    regexes: Vec<(String, Regex)>,
+}
+
+pub struct RouteMatch<'a> {
+    /// key of the matched route in `routes`
+    pub key: &'a str,
+    pub params: Params,
+    /// path left over after a prefix or trailing wildcard, without leading '/'
+    pub rest: String,
+}
+
+struct Pattern {
+    key: String,
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Router {
+    pub fn new(routes: &HashMap<String, RouteConfig>) -> Result<Self, RouterError> {
+        let mut router = Self {
+            exact: HashSet::new(),
+            prefixes: Vec::new(),
+            patterns: Vec::new(),
+            regexes: Vec::new(),
+        };
+
+        for (key, route) in routes {
+            for action in route.methods.values() {
+                check_action(key, action)?;
+            }
+
+            if let Some(expr) = key.strip_prefix('~') {
+                let regex = Regex::new(expr.trim()).map_err(|e| RouterError::InvalidRegex {
+                    route: key.clone(),
+                    source: Box::new(e),
+                })?;
+                router.regexes.push((key.clone(), regex));
+            } else if key.contains(['{', '*']) {
+                router.patterns.push(Pattern::parse(key)?);
+            } else if route.prefix {
+                router.prefixes.push(key.clone());
+            } else {
+                router.exact.insert(key.clone());
+            }
+        }
+
+        router
+            .prefixes
+            .sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        router.patterns.sort_by(|a, b| {
+            b.literals()
+                .cmp(&a.literals())
+                .then(a.has_wildcard().cmp(&b.has_wildcard()))
+                .then(b.segments.len().cmp(&a.segments.len()))
+                .then(a.key.cmp(&b.key))
+        });
+        router.regexes.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(router)
+    }
+
+    pub fn find(&self, path: &str) -> Option<RouteMatch<'_>> {
+        if let Some(key) = self.exact.get(path) {
+            return Some(RouteMatch {
+                key,
+                params: Params::new(),
+                rest: String::new(),
+            });
+        }
+
+        for prefix in &self.prefixes {
+            if let Some(rest) = strip_path_prefix(path, prefix) {
+                let rest = rest.trim_start_matches('/').to_string();
+                return Some(RouteMatch {
+                    key: prefix,
+                    params: vec![("rest".to_string(), rest.clone())],
+                    rest,
+                });
+            }
+        }
+
+        for pattern in &self.patterns {
+            if let Some(found) = pattern.matches(path) {
+                return Some(found);
+            }
+        }
+
+        for (key, regex) in &self.regexes {
+            if let Some(captures) = regex.captures(path) {
+                let params = regex
+                    .capture_names()
+                    .flatten()
+                    .filter_map(|name| {
+                        let value = captures.name(name)?.as_str();
+                        Some((name.to_string(), value.to_string()))
+                    })
+                    .collect();
+
+                return Some(RouteMatch {
+                    key,
+                    params,
+                    rest: String::new(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl Pattern {
+    fn parse(key: &str) -> Result<Self, RouterError> {
+        let invalid = |reason: &str| RouterError::InvalidPattern {
+            route: key.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let parts: Vec<&str> = key.trim_start_matches('/').split('/').collect();
+        let mut segments = Vec::with_capacity(parts.len());
+
+        for (i, part) in parts.iter().enumerate() {
+            let segment = if let Some(name) = part.strip_prefix('*') {
+                if i + 1 != parts.len() {
+                    return Err(invalid("a wildcard must be the last segment"));
+                }
+                Segment::Wildcard(name.to_string())
+            } else if let Some(name) = part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                Segment::Param(name.to_string())
+            } else if part.contains(['{', '}', '*']) {
+                return Err(invalid("parameters must span a whole segment"));
+            } else {
+                Segment::Literal(part.to_string())
+            };
+
+            if let Segment::Param(name) | Segment::Wildcard(name) = &segment
+                && name.is_empty()
+            {
+                return Err(invalid("parameters need a name"));
+            }
+
+            segments.push(segment);
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            segments,
+        })
+    }
+
+    fn literals(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|s| matches!(s, Segment::Literal(_)))
+            .count()
+    }
+
+    fn has_wildcard(&self) -> bool {
+        matches!(self.segments.last(), Some(Segment::Wildcard(_)))
+    }
+
+    fn matches(&self, path: &str) -> Option<RouteMatch<'_>> {
+        let mut parts = path.trim_start_matches('/').splitn(
+            // leave whatever a wildcard covers in one piece
+            if self.has_wildcard() {
+                self.segments.len()
+            } else {
+                usize::MAX
+            },
+            '/',
+        );
+
+        let mut params = Params::new();
+        let mut rest = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let value = parts.next().filter(|v| !v.is_empty())?;
+                    params.push((name.clone(), value.to_string()));
+                }
+                Segment::Wildcard(name) => {
+                    rest = parts.next().unwrap_or_default().to_string();
+                    params.push((name.clone(), rest.clone()));
+                }
+            }
+        }
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(RouteMatch {
+            key: &self.key,
+            params,
+            rest,
+        })
+    }
+}
+
+pub fn is_redirect_status(status: u16) -> bool {
+    matches!(status, 301 | 302 | 303 | 307 | 308)
+}
+
+/// what can be told about `action` before any request; `route` names it in errors
+pub fn check_action(route: &str, action: &Action) -> Result<(), RouterError> {
+    match action {
+        Action::Redirect { status, .. } if !is_redirect_status(*status) => {
+            Err(RouterError::InvalidRedirectStatus {
+                route: route.to_string(),
+                status: *status,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// substitute `{name}` placeholders in `template`; unknown names are left as they are.
+/// one pass over the template, so braces inside a value are never expanded again
+pub fn expand(template: &str, params: &[(String, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            let (_, value) = params.iter().find(|(n, _)| n == name)?;
+            Some((value, close))
+        });
+
+        match value {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+// segment-wise: "/assets" covers "/assets" and "/assets/x" but not "/assetsx"
+fn strip_path_prefix<'p>(path: &'p str, prefix: &str) -> Option<&'p str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HeaderRules;
+
+    fn route(prefix: bool) -> RouteConfig {
+        RouteConfig {
+            methods: HashMap::new(),
+            prefix,
+            cache: Default::default(),
+            validators: Default::default(),
+            max_body_size: None,
+            headers: HeaderRules::default(),
+            upstream_headers: HeaderRules::default(),
+            require_client: Vec::new(),
+            replay_safe: None,
+            rate_limit: None,
+        }
+    }
+
+    // keys ending in "/**" are prefix routes, without those three characters
+    fn router(keys: &[&str]) -> Router {
+        let routes = keys
+            .iter()
+            .map(|key| match key.strip_suffix("/**") {
+                Some(key) => (key.to_string(), route(true)),
+                None => (key.to_string(), route(false)),
+            })
+            .collect();
+        Router::new(&routes).unwrap()
+    }
+
+    fn find<'r>(router: &'r Router, path: &str) -> Option<(&'r str, Params, String)> {
+        router
+            .find(path)
+            .map(|found| (found.key, found.params, found.rest))
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn exact_then_longest_prefix_then_pattern_then_regex() {
+        let router = router(&[
+            "/api/users/me",
+            "/api/**",
+            "/api/users/**",
+            "/api/users/{id}",
+            "~^/api/users/(?P<id>[0-9]+)$",
+            "~^/img/(?P<name>.+)$",
+        ]);
+
+        assert_eq!(find(&router, "/api/users/me").unwrap().0, "/api/users/me");
+        // the longer prefix beats both the shorter one and the pattern
+        let (key, params_found, rest) = find(&router, "/api/users/7").unwrap();
+        assert_eq!(key, "/api/users");
+        assert_eq!(params_found, params(&[("rest", "7")]));
+        assert_eq!(rest, "7");
+        assert_eq!(find(&router, "/api/orders/1").unwrap().0, "/api");
+        // nothing else matches, so the regex does
+        let (key, params_found, _) = find(&router, "/img/a/b.png").unwrap();
+        assert_eq!(key, "~^/img/(?P<name>.+)$");
+        assert_eq!(params_found, params(&[("name", "a/b.png")]));
+        assert!(find(&router, "/other").is_none());
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let router = router(&["/api/**"]);
+
+        assert_eq!(find(&router, "/api").unwrap().2, "");
+        assert_eq!(find(&router, "/api/").unwrap().2, "");
+        assert_eq!(find(&router, "/api/x/y").unwrap().2, "x/y");
+        assert!(find(&router, "/apiary").is_none());
+        assert!(find(&router, "/ap").is_none());
+    }
+
+    #[test]
+    fn patterns_capture_segments_and_the_rest() {
+        let router = router(&[
+            "/users/{id}",
+            "/users/{id}/posts/{post}",
+            "/users/me",
+            "/files/*path",
+        ]);
+
+        let (key, found, _) = find(&router, "/users/42").unwrap();
+        assert_eq!(key, "/users/{id}");
+        assert_eq!(found, params(&[("id", "42")]));
+        assert_eq!(find(&router, "/users/me").unwrap().0, "/users/me");
+        assert_eq!(
+            find(&router, "/users/42/posts/9").unwrap().1,
+            params(&[("id", "42"), ("post", "9")])
+        );
+        // a parameter is one whole, non-empty segment
+        assert!(find(&router, "/users/").is_none());
+        assert!(find(&router, "/users/42/posts").is_none());
+
+        let (key, found, rest) = find(&router, "/files/a/b/c.txt").unwrap();
+        assert_eq!(key, "/files/*path");
+        assert_eq!(found, params(&[("path", "a/b/c.txt")]));
+        assert_eq!(rest, "a/b/c.txt");
+        assert_eq!(find(&router, "/files").unwrap().2, "");
+    }
+
+    #[test]
+    fn patterns_with_more_literals_win() {
+        let router = router(&["/{a}/{b}", "/docs/{page}", "/docs/*rest"]);
+
+        assert_eq!(find(&router, "/docs/intro").unwrap().0, "/docs/{page}");
+        assert_eq!(find(&router, "/docs/a/b").unwrap().0, "/docs/*rest");
+        assert_eq!(find(&router, "/blog/intro").unwrap().0, "/{a}/{b}");
+    }
+
+    #[test]
+    fn regexes_are_tried_in_key_order_after_everything_else() {
+        let router = router(&["~^/a", "~^/a/b", "/a/{x}"]);
+
+        assert_eq!(find(&router, "/a/b").unwrap().0, "/a/{x}");
+        assert_eq!(find(&router, "/a/b/c").unwrap().0, "~^/a");
+        assert!(find(&router, "/b").is_none());
+    }
+
+    #[test]
+    fn bad_keys_are_refused() {
+        for key in ["/a/*rest/b", "/a/x{id}", "/a/{}", "~(unclosed"] {
+            let routes = HashMap::from([(key.to_string(), route(false))]);
+            assert!(Router::new(&routes).is_err(), "{key}");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use crate::config::{RouteConfig, Server, ServerTlsConf, StandardResponses};
+use crate::helpers::host;
+
+/// what answers requests for one hostname: a virtual host, or the server itself
+pub struct Host<'a> {
+    /// key in `Server.hosts`; None for the server itself
+    pub name: Option<&'a str>,
+    pub routes: &'a HashMap<String, RouteConfig>,
+    pub standard: &'a StandardResponses,
+    pub tls: Option<&'a ServerTlsConf>,
+}
+
+/// the host of `server` answering `name` (an authority or sni value), falling
+/// back to the default host. None means the request is misdirected.
+pub fn select<'a>(server: &'a Server, name: Option<&str>) -> Option<Host<'a>> {
+    if server.hosts.is_empty() {
+        return Some(Host {
+            name: None,
+            routes: &server.routes,
+            standard: &server.standard,
+            tls: server.tls.as_ref(),
+        });
+    }
+
+    let key = name
+        .map(host::normalize)
+        .and_then(|name| host::find(server.hosts.keys().map(String::as_str), &name))
+        .or(server.default_host.as_deref())?;
+    let (key, vhost) = server.hosts.get_key_value(key)?;
+
+    Some(Host {
+        name: Some(key),
+        routes: &vhost.routes,
+        standard: vhost.standard.as_ref().unwrap_or(&server.standard),
+        tls: vhost.tls.as_ref().or(server.tls.as_ref()),
+    })
+}
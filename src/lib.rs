@@ -9,3 +9,4 @@ pub mod helpers;
 pub mod http;
 pub mod logging;
 pub mod net;
+pub mod state;
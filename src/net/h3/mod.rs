@@ -1,23 +1,31 @@
 mod error;
 
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Waker};
 
 use h3::ext::Protocol;
 use h3_quinn::Connection as H3QuinnConnection;
 use h3_webtransport::server::WebTransportSession;
 use http::Method;
-use quinn::Connection;
+use quinn::{Connection, ZeroRttAccepted};
 use tracing::{debug, error, info};
 
 use crate::config::AppConfig;
+use crate::http::peer::Peer;
 use crate::http::request;
 use crate::net::h3::error::ServerError;
+use crate::state::State;
 
 use tokio::task::JoinSet;
 
+/// `handshake` is given when the connection was taken with 0-rtt, and
+/// resolves once the handshake is done
 pub async fn handle_connection(
     conn: Connection,
+    handshake: Option<ZeroRttAccepted>,
     config: Arc<AppConfig>,
+    state: Arc<State>,
     server_name: Arc<String>,
 ) -> Result<(), ServerError> {
     let server_config = config
@@ -25,6 +33,11 @@ pub async fn handle_connection(
         .get(&*server_name)
         .ok_or_else(|| ServerError::MissingServerConfig(server_name.to_string()))?;
 
+    let peer = Arc::new(Peer::new(&conn));
+    let mut handshake = handshake;
+    note_handshake(&peer, &conn, &mut handshake);
+    let quic = conn.clone();
+
     // build http3 conn
     let mut builder_base = h3::server::builder();
     let builder_extended = builder_base.enable_extended_connect(true);
@@ -84,13 +97,24 @@ pub async fn handle_connection(
                     return Ok(());
                 }
 
+                note_handshake(&peer, &quic, &mut handshake);
+
                 let config_clone = config.clone();
+                let state_clone = state.clone();
                 let server_name_clone = server_name.clone();
                 let server_name_clone_2 = server_name.clone();
+                let peer_clone = peer.clone();
 
                 join_set.spawn(async move {
-                    if let Err(e) =
-                        request::handle_request(req, stream, config_clone, server_name_clone).await
+                    if let Err(e) = request::handle_request(
+                        req,
+                        stream,
+                        config_clone,
+                        state_clone,
+                        server_name_clone,
+                        peer_clone,
+                    )
+                    .await
                     {
                         error!(server = %server_name_clone_2, error = %e, "http3_request_error");
                     }
@@ -119,3 +143,116 @@ pub async fn handle_connection(
 
     Ok(())
 }
+
+// requests stop counting as early once the handshake is done. checked as each
+// request is resolved: the connection driver reports the client's finished
+// before it hands over any stream data that came after it, so a request sent
+// once the handshake was done never sees it pending
+fn note_handshake(peer: &Peer, conn: &Connection, handshake: &mut Option<ZeroRttAccepted>) {
+    if let Some(accepted) = handshake {
+        // on a server this resolves to false either way; what matters is that it did
+        let mut cx = Context::from_waker(Waker::noop());
+        if Pin::new(accepted).poll(&mut cx).is_pending() {
+            return;
+        }
+        *handshake = None;
+        if conn.close_reason().is_some() {
+            return;
+        }
+    }
+    peer.complete(conn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+    use quinn::{ClientConfig, Endpoint, ServerConfig};
+    use rustls::crypto::aws_lc_rs;
+    use rustls::pki_types::PrivateKeyDer;
+
+    fn endpoints() -> (Endpoint, Endpoint) {
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+        let key = PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into());
+
+        let mut server = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        server.max_early_data_size = u32::MAX;
+        let server =
+            ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server).unwrap()));
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let mut client = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.enable_early_data = true;
+        let client = ClientConfig::new(Arc::new(QuicClientConfig::try_from(client).unwrap()));
+
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let server = Endpoint::server(server, localhost).unwrap();
+        let mut endpoint = Endpoint::client(localhost).unwrap();
+        endpoint.set_default_client_config(client);
+        (server, endpoint)
+    }
+
+    // a connection the way the accept loop takes it with early data on
+    async fn accept(server: &Endpoint) -> (Connection, Peer, Option<ZeroRttAccepted>) {
+        let mut connecting = server.accept().await.unwrap().accept().unwrap();
+        connecting.handshake_data().await.unwrap();
+        let (conn, accepted) = connecting.into_0rtt().ok().unwrap();
+        let peer = Peer::new(&conn);
+        let mut handshake = Some(accepted);
+        note_handshake(&peer, &conn, &mut handshake);
+        (conn, peer, handshake)
+    }
+
+    // a request, as far as the handshake is concerned
+    async fn request(client: &Connection, server: &Connection) {
+        let mut uni = client.open_uni().await.unwrap();
+        uni.write_all(b"x").await.unwrap();
+        uni.finish().unwrap();
+        let mut recv = server.accept_uni().await.unwrap();
+        recv.read_to_end(1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_after_the_handshake_are_not_early() {
+        let (server, client) = endpoints();
+        let addr = server.local_addr().unwrap();
+
+        // a full handshake, which also leaves the client a ticket to resume with
+        let (client_conn, (conn, peer, mut handshake)) = tokio::join!(
+            async { client.connect(addr, "localhost").unwrap().await.unwrap() },
+            accept(&server),
+        );
+        request(&client_conn, &conn).await;
+        note_handshake(&peer, &conn, &mut handshake);
+        assert!(!peer.early());
+        // the ticket comes before the close
+        conn.close(0u32.into(), b"");
+        client_conn.closed().await;
+
+        // resumed with 0-rtt: the first request once the client is done counts as 1-rtt
+        let (client_conn, client_accepted) = client
+            .connect(addr, "localhost")
+            .unwrap()
+            .into_0rtt()
+            .unwrap_or_else(|_| panic!("no ticket to resume with"));
+        let (conn, peer, mut handshake) = accept(&server).await;
+
+        assert!(client_accepted.await);
+        request(&client_conn, &conn).await;
+        note_handshake(&peer, &conn, &mut handshake);
+        assert!(!peer.early());
+    }
+}
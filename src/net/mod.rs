@@ -6,6 +6,7 @@ use socket2::{Domain, Protocol, Socket, Type};
 use tracing::{debug, error, info};
 
 use crate::config::AppConfig;
+use crate::state::State;
 
 pub mod h3;
 pub mod quic;
@@ -16,6 +17,7 @@ use crate::net::quic::{ConnectionError, accept_loop};
 
 pub async fn run_server(
     config: Arc<AppConfig>,
+    state: Arc<State>,
     server_name: String,
     signals: Arc<SignalHandler>,
 ) -> Result<(), ConnectionError> {
@@ -34,22 +36,38 @@ pub async fn run_server(
 
     let listen_addr = resolve_ipv6_addr(&server_config.host, server_config.port).await?;
 
-    let tls_config = if let Some(tls_conf) = &server_config.tls {
-        tls::load_or_generate(&server_name, Some(&tls_conf.cert), Some(&tls_conf.key)).await?
-    } else {
-        tls::load_or_generate(&server_name, None, None).await?
-    };
+    let (tls_config, certs, tickets) =
+        tls::server_config(&config, &server_name, server_config).await?;
 
     debug!(alpn = ?tls_config.alpn_protocols, "tls_alpn_configured");
 
-    let endpoint = create_endpoint(&listen_addr, tls_config).await?;
+    let transport = quic::transport::build(&server_config.transport)?;
+    debug!(server = %server_name, transport = ?server_config.transport, "quic_transport_configured");
+
+    let endpoint = create_endpoint(&listen_addr, tls_config, transport).await?;
 
     info!(server = %server_name, addr = %listen_addr, "connection_listening");
 
+    #[cfg(feature = "acme")]
+    let acme = state.acme.spawn(
+        config.clone(),
+        server_name.clone(),
+        listen_addr,
+        certs.clone(),
+    );
+    let reload = tls::reload::spawn(config.clone(), server_name.clone(), certs, tickets);
+
     // use unified accept loop
     let result =
-        accept_loop::run_accept_loop(endpoint, Arc::clone(&config), server_name.clone(), signals)
-            .await;
+        accept_loop::run_accept_loop(endpoint, config, state, server_name.clone(), signals).await;
+
+    if let Some(reload) = reload {
+        reload.abort();
+    }
+    #[cfg(feature = "acme")]
+    if let Some(acme) = acme {
+        acme.abort();
+    }
 
     match &result {
         Ok(_) => info!(server = %server_name, "connection_closed_clean"),
@@ -84,6 +102,7 @@ async fn resolve_ipv6_addr(host: &str, port: u16) -> Result<SocketAddr, Connecti
 async fn create_endpoint(
     listen_addr: &SocketAddr,
     tls_config: rustls::ServerConfig,
+    transport: quinn::TransportConfig,
 ) -> Result<Endpoint, ConnectionError> {
     let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))
         .map_err(ConnectionError::SocketCreation)?;
@@ -105,7 +124,8 @@ async fn create_endpoint(
             ))
         })?;
 
-    let server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_server_config));
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_server_config));
+    server_config.transport_config(Arc::new(transport));
     let endpoint_config = EndpointConfig::default();
 
     Endpoint::new(
@@ -4,37 +4,96 @@ use std::sync::Arc;
 use tracing::{debug, info};
 
 use super::error::ConnectionError;
+use super::limits::{Admission, Limiter};
 use crate::config::AppConfig;
 use crate::net::h3;
+use crate::state::State;
 
 // unified accept loop for http3 (also calls wt handler)
 pub async fn run_accept_loop(
     endpoint: Endpoint,
     config: Arc<AppConfig>,
+    state: Arc<State>,
     server_name: String,
     signals: Arc<SignalHandler>,
 ) -> Result<(), ConnectionError> {
     let server_name = Arc::new(server_name);
     info!(server = %server_name, "accept_loop_start");
 
+    let server = config
+        .servers
+        .get(&*server_name)
+        .ok_or_else(|| ConnectionError::ServerNotFound(server_name.to_string()))?;
+    let early_data = server
+        .tls
+        .as_ref()
+        .is_some_and(|tls| tls.max_early_data_size > 0);
+    let limiter = Limiter::new(server.connections.clone());
+
     loop {
         tokio::select! {
             incoming = endpoint.accept() => {
                 if let Some(incoming) = incoming {
+                    let remote = incoming.remote_address();
+                    let permit = match limiter.admit(&incoming) {
+                        Admission::Accept(permit) => permit,
+                        Admission::Retry => {
+                            debug!(server = %server_name, remote = %remote, "connection_retry");
+                            if let Err(e) = incoming.retry() {
+                                e.into_incoming().refuse();
+                            }
+                            continue;
+                        }
+                        Admission::Refuse(reason) => {
+                            debug!(server = %server_name, remote = %remote, reason, open = limiter.open(), "connection_refused");
+                            incoming.refuse();
+                            continue;
+                        }
+                    };
+
                     let config = config.clone();
+                    let state = state.clone();
                     let server_name = server_name.clone();
 
                     tokio::spawn(async move {
-                        match incoming.await {
-                            Ok(conn) => {
-                                let remote = conn.remote_address();
-                                info!(server = %server_name, remote = %remote, "connection_established");
+                        // counted until the connection is gone
+                        let _permit = permit;
 
-                                if let Err(e) = h3::handle_connection(conn, config.clone(), server_name.clone()).await {
-                                    debug!(server = %server_name, remote = %remote, error = %e,);
-                                }
+                        let mut connecting = match incoming.accept() {
+                            Ok(connecting) => connecting,
+                            Err(e) => {
+                                debug!(server = %server_name, error = %e,);
+                                return;
                             }
-                            Err(e) => debug!(server = %server_name, error = %e,),
+                        };
+
+                        // with 0-rtt, requests are taken before the handshake is done,
+                        // but not before the client hello: virtual hosts need its sni
+                        if early_data && let Err(e) = connecting.handshake_data().await {
+                            debug!(server = %server_name, error = %e,);
+                            return;
+                        }
+
+                        let zero_rtt = if early_data {
+                            connecting.into_0rtt().map(|(conn, accepted)| (conn, Some(accepted)))
+                        } else {
+                            Err(connecting)
+                        };
+                        let (conn, handshake) = match zero_rtt {
+                            Ok(accepted) => accepted,
+                            Err(connecting) => match connecting.await {
+                                Ok(conn) => (conn, None),
+                                Err(e) => {
+                                    debug!(server = %server_name, error = %e,);
+                                    return;
+                                }
+                            },
+                        };
+
+                        info!(server = %server_name, remote = %remote, early_data = handshake.is_some(), "connection_established");
+
+                        if let Err(e) = h3::handle_connection(conn, handshake, config, state, server_name.clone()).await {
+                            debug!(server = %server_name, remote = %remote, error = %e,);
                         }
                     });
                 }
@@ -26,6 +26,9 @@ pub enum ConnectionError {
     #[error("failed to create QUIC endpoint: {0}")]
     EndpointCreation(io::Error),
 
+    #[error("transport.{field} is out of range: {value}")]
+    TransportValue { field: &'static str, value: u64 },
+
     #[error("TLS configuration error: {0}")]
     Tls(#[from] TlsError),
 }
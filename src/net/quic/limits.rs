@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use quinn::Incoming;
+
+use crate::config::Connections;
+use crate::helpers::net;
+
+/// what to do with a connection attempt
+pub enum Admission {
+    Accept(Permit),
+    // make the client prove it owns its address first
+    Retry,
+    Refuse(&'static str),
+}
+
+/// connections open on one server, in total and per client address block
+pub struct Limiter {
+    config: Connections,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    open: usize,
+    // blocks without connections are removed, so this stays as large as `open` at most
+    per_client: HashMap<IpAddr, usize>,
+    // attempts in the current one second window
+    window: Option<(Instant, u32)>,
+}
+
+/// one accepted connection; it stops counting when dropped
+pub struct Permit {
+    limiter: Arc<Limiter>,
+    // None when the address wasn't validated
+    client: Option<IpAddr>,
+}
+
+impl Limiter {
+    pub fn new(config: Connections) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub fn admit(self: &Arc<Self>, incoming: &Incoming) -> Admission {
+        let client = net::prefix(
+            incoming.remote_address().ip(),
+            self.config.client_prefix_v4,
+            self.config.client_prefix_v6,
+        );
+
+        let validated = incoming.remote_address_validated();
+
+        let mut state = self.lock();
+        let rate = state.count_attempt();
+
+        let max = self.config.max_connections;
+        let from_client = state.per_client.get(&client).copied().unwrap_or(0);
+        let client_full =
+            self.config.max_per_client > 0 && from_client >= self.config.max_per_client;
+
+        // an unvalidated address may be spoofed: under load it gets a retry before
+        // it can take a slot, and it has to be proven before it counts against a
+        // block. a retry only helps once; addresses that came back with a token
+        // are validated
+        let busy = self
+            .config
+            .retry_above_per_sec
+            .is_some_and(|threshold| rate > threshold);
+        let near_full = max > 0 && state.open >= max - max / 4;
+        if !validated
+            && incoming.may_retry()
+            && (busy || near_full || self.config.max_per_client > 0)
+        {
+            return Admission::Retry;
+        }
+
+        if max > 0 && state.open >= max {
+            return Admission::Refuse("server_full");
+        }
+        if client_full {
+            return Admission::Refuse("client_full");
+        }
+
+        // only a proven address counts against its block
+        let client = validated.then_some(client);
+        state.open += 1;
+        if let Some(client) = client {
+            *state.per_client.entry(client).or_default() += 1;
+        }
+
+        Admission::Accept(Permit {
+            limiter: self.clone(),
+            client,
+        })
+    }
+
+    /// connections open now
+    pub fn open(&self) -> usize {
+        self.lock().open
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    // attempts so far this second, this one included
+    fn count_attempt(&mut self) -> u32 {
+        let now = Instant::now();
+        match &mut self.window {
+            Some((start, count)) if now.duration_since(*start) < Duration::from_secs(1) => {
+                *count = count.saturating_add(1);
+                *count
+            }
+            window => {
+                *window = Some((now, 1));
+                1
+            }
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.lock();
+        state.open = state.open.saturating_sub(1);
+        if let Some(client) = self.client
+            && let Some(count) = state.per_client.get_mut(&client)
+        {
+            *count -= 1;
+            if *count == 0 {
+                state.per_client.remove(&client);
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
 pub mod accept_loop;
 mod error;
+mod limits;
+pub mod transport;
 
 pub use error::ConnectionError;
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+use quinn::{IdleTimeout, MtuDiscoveryConfig, TransportConfig, VarInt};
+
+use super::ConnectionError;
+use crate::config::transport::{Congestion, Transport};
+
+const H3_UNI_STREAMS: u32 = 3;
+
+/// quinn transport config for `transport`, starting from quinn's defaults
+pub fn build(transport: &Transport) -> Result<TransportConfig, ConnectionError> {
+    let mut config = TransportConfig::default();
+
+    if let Some(secs) = transport.idle_timeout_secs {
+        let timeout = match secs {
+            0 => None,
+            secs => Some(
+                IdleTimeout::try_from(Duration::from_secs(secs))
+                    .map_err(|_| out_of_range("idle_timeout_secs", secs))?,
+            ),
+        };
+        config.max_idle_timeout(timeout);
+    }
+
+    if let Some(secs) = transport.keep_alive_interval_secs {
+        config.keep_alive_interval((secs > 0).then(|| Duration::from_secs(secs)));
+    }
+
+    if let Some(streams) = transport.max_concurrent_bidi_streams {
+        config.max_concurrent_bidi_streams(VarInt::from_u32(streams));
+    }
+    if let Some(streams) = transport.max_concurrent_uni_streams {
+        // http/3 clients open three of their own: control and both qpack streams
+        if streams < H3_UNI_STREAMS {
+            return Err(out_of_range("max_concurrent_uni_streams", streams.into()));
+        }
+        config.max_concurrent_uni_streams(VarInt::from_u32(streams));
+    }
+
+    if let Some(window) = transport.stream_receive_window {
+        config.stream_receive_window(var_int("stream_receive_window", window)?);
+    }
+    if let Some(window) = transport.receive_window {
+        config.receive_window(var_int("receive_window", window)?);
+    }
+
+    if let Some(ms) = transport.initial_rtt_ms {
+        config.initial_rtt(Duration::from_millis(ms));
+    }
+
+    config.mtu_discovery_config(transport.mtu_discovery.then(MtuDiscoveryConfig::default));
+
+    match transport.congestion {
+        Congestion::Cubic => config.congestion_controller_factory(Arc::new(CubicConfig::default())),
+        Congestion::NewReno => {
+            config.congestion_controller_factory(Arc::new(NewRenoConfig::default()))
+        }
+        Congestion::Bbr => config.congestion_controller_factory(Arc::new(BbrConfig::default())),
+    };
+
+    Ok(config)
+}
+
+fn var_int(field: &'static str, value: u64) -> Result<VarInt, ConnectionError> {
+    VarInt::from_u64(value).map_err(|_| out_of_range(field, value))
+}
+
+fn out_of_range(field: &'static str, value: u64) -> ConnectionError {
+    ConnectionError::TransportValue { field, value }
+}
@@ -25,6 +25,39 @@ pub enum TlsError {
     #[error("failed to write generated private key to '{path}': {source}")]
     PrivateKeyWrite { path: String, source: io::Error },
 
+    #[error("client_auth is on but no client_ca is given")]
+    ClientCaMissing,
+
+    #[error("failed to read crl from '{path}': {source}")]
+    CrlRead { path: String, source: io::Error },
+
+    #[error("invalid crl in '{path}'")]
+    InvalidCrl { path: String },
+
+    #[error("certificate '{path}' is listed without any names")]
+    NoNames { path: String },
+
+    #[error("unknown cipher suite '{0}'")]
+    UnknownCipherSuite(String),
+
+    #[error("unknown key exchange group '{0}'")]
+    UnknownKxGroup(String),
+
+    #[error("tls policy can't be used with quic: {0}")]
+    QuicIncompatible(String),
+
+    #[error("ticket_key_file is set but session_tickets is off")]
+    TicketKeyUnused,
+
+    #[error("failed to read ticket keys from '{path}': {source}")]
+    TicketKeyRead { path: String, source: io::Error },
+
+    #[error("invalid ticket key in '{path}': each line must be 64 hex digits")]
+    InvalidTicketKey { path: String },
+
+    #[error("max_early_data_size needs session_tickets or a session cache to resume from")]
+    EarlyDataWithoutResumption,
+
     #[error("failed to create TLS configuration: {0}")]
     ConfigCreation(String),
 }
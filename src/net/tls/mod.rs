@@ -1,18 +1,166 @@
 pub mod error;
+pub mod reload;
+mod resolver;
+mod tickets;
 
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use chrono::Utc;
+use rustls::RootCertStore;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use rustls::pki_types::{
+    CertificateDer, CertificateRevocationListDer, PrivateKeyDer, pem::PemObject,
+};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{NoServerSessionStorage, ServerSessionMemoryCache, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
 use tracing::{info, warn};
 
+use crate::config::tls::{ClientAuth, TlsVersion};
+use crate::config::{AppConfig, Server, ServerTlsConf};
+use crate::helpers::{host, x509};
 use error::TlsError;
+pub use resolver::{Certs, SniResolver};
+pub use tickets::KeyFileTicketer;
+
+// quic protects its initial packets with this suite (rfc 9001 5.2)
+const QUIC_INITIAL_SUITE: &str = "TLS13_AES_128_GCM_SHA256";
+
+/// tls config for a server: its own certificate, the ones it lists, and those
+/// of its virtual hosts, chosen by sni. the resolver can be handed new
+/// certificates later, and the ticketer new keys, see `reload`.
+pub async fn server_config(
+    config: &AppConfig,
+    server_name: &str,
+    server: &Server,
+) -> Result<
+    (
+        rustls::ServerConfig,
+        Arc<SniResolver>,
+        Option<Arc<KeyFileTicketer>>,
+    ),
+    TlsError,
+> {
+    let certs = load_certs(config, server_name, server).await?;
+
+    let (missing, unknown) = server
+        .tls
+        .as_ref()
+        .map_or_else(Default::default, |tls| (tls.missing_sni, tls.unknown_sni));
+    let resolver = Arc::new(SniResolver::new(certs, missing, unknown));
+    let verifier = client_verifier(server_name, server.tls.as_ref()).await?;
+    let (config, tickets) =
+        build_server_config(server_name, server.tls.as_ref(), verifier, resolver.clone())?;
+
+    Ok((config, resolver, tickets))
+}
+
+/// every certificate `server` presents, read from disk (or generated)
+pub async fn load_certs(
+    config: &AppConfig,
+    server_name: &str,
+    server: &Server,
+) -> Result<Certs, TlsError> {
+    let acme = load_acme(config, server_name, server).await;
+
+    let own = match (&server.tls, &acme) {
+        (Some(tls), _) => load_or_generate(server_name, Some(&tls.cert), Some(&tls.key)).await?,
+        (None, Some(key)) => key.clone(),
+        (None, None) => load_or_generate(server_name, None, None).await?,
+    };
+
+    let mut names = Vec::new();
+    if let Some(tls) = &server.tls {
+        load_listed(server_name, tls, &mut names).await?;
+    }
+    if let Some(key) = acme {
+        for name in &server.acme {
+            names.push((host::normalize(name), key.clone()));
+        }
+    }
+
+    let mut hosts = Vec::new();
+    for (host, vhost) in &server.hosts {
+        if let Some(tls) = &vhost.tls {
+            info!(server = server_name, host = %host, "tls_host_certificate");
+            load_listed(server_name, tls, &mut names).await?;
+            let key = load_from_files(server_name, &tls.cert, &tls.key).await?;
+            hosts.push((host.clone(), key));
+        }
+    }
+
+    // names no host matches are answered by the default host, so they get its certificate
+    let fallback = server
+        .default_host
+        .as_ref()
+        .and_then(|default| hosts.iter().find(|(host, _)| host == default))
+        .map_or(own, |(_, key)| key.clone());
+    names.extend(hosts);
+
+    Ok(Certs { names, fallback })
+}
+
+// the certificate an acme ca issued for `server`; until there is one, or while
+// it's unusable, the names fall back like any unknown sni
+async fn load_acme(
+    config: &AppConfig,
+    server_name: &str,
+    server: &Server,
+) -> Option<Arc<CertifiedKey>> {
+    if server.acme.is_empty() {
+        return None;
+    }
+
+    let (cert, key) = config.acme.cert_paths(server_name);
+    if !cert.exists() {
+        info!(server = server_name, "tls_acme_certificate_pending");
+        return None;
+    }
+
+    match load_from_files(server_name, &cert, &key).await {
+        Ok(key) => Some(key),
+        Err(e) => {
+            warn!(server = server_name, error = %e, "tls_acme_certificate_unusable");
+            None
+        }
+    }
+}
+
+// certificates listed in `tls.certificates`, once for each of their names
+async fn load_listed(
+    server_name: &str,
+    tls: &ServerTlsConf,
+    names: &mut Vec<(String, Arc<CertifiedKey>)>,
+) -> Result<(), TlsError> {
+    for listed in &tls.certificates {
+        if listed.names.is_empty() {
+            return Err(TlsError::NoNames {
+                path: listed.cert.display().to_string(),
+            });
+        }
+
+        let key = load_from_files(server_name, &listed.cert, &listed.key).await?;
+        info!(
+            server = server_name,
+            cert = %listed.cert.display(),
+            names = ?listed.names,
+            "tls_sni_certificate"
+        );
+        for name in &listed.names {
+            names.push((host::normalize(name), key.clone()));
+        }
+    }
+
+    Ok(())
+}
 
 // gen self signed or load if given correctly.
 pub async fn load_or_generate(
     server_name: &str,
     cert_path: Option<&PathBuf>,
     key_path: Option<&PathBuf>,
-) -> Result<rustls::ServerConfig, TlsError> {
+) -> Result<Arc<CertifiedKey>, TlsError> {
     match (cert_path, key_path) {
         // Both paths provided - load from disk
         (Some(cert), Some(key)) => {
@@ -71,7 +219,7 @@ async fn load_from_files(
     server_name: &str,
     cert_path: &Path,
     key_path: &Path,
-) -> Result<rustls::ServerConfig, TlsError> {
+) -> Result<Arc<CertifiedKey>, TlsError> {
     let cert_pem = match tokio::fs::read(cert_path).await {
         Ok(bytes) => bytes,
         Err(e) => {
@@ -132,14 +280,14 @@ async fn load_from_files(
         }
     };
 
-    build_server_config(server_name, certs, key)
+    certified_key(server_name, certs, key)
 }
 
 async fn generate_and_save(
     server_name: &str,
     cert_path: &Path,
     key_path: &Path,
-) -> Result<rustls::ServerConfig, TlsError> {
+) -> Result<Arc<CertifiedKey>, TlsError> {
     let cert = rcgen::generate_simple_self_signed(vec![server_name.to_string()])
         .map_err(|e| TlsError::Generation(e.to_string()))?;
 
@@ -178,25 +326,235 @@ async fn generate_and_save(
     load_from_files(server_name, cert_path, key_path).await
 }
 
-fn build_server_config(
+fn certified_key(
     server_name: &str,
     certs: Vec<CertificateDer<'static>>,
     key: PrivateKeyDer<'static>,
-) -> Result<rustls::ServerConfig, TlsError> {
-    match rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-    {
-        Ok(mut config) => {
-            config.alpn_protocols = vec![b"h3".to_vec()];
-            Ok(config)
-        }
+) -> Result<Arc<CertifiedKey>, TlsError> {
+    let provider = rustls::crypto::CryptoProvider::get_default()
+        .ok_or_else(|| TlsError::ConfigCreation("no crypto provider installed".into()))?;
+
+    let key = match CertifiedKey::from_der(certs, key, provider) {
+        Ok(key) => key,
         Err(e) => {
             warn!(
                 server = server_name,
-                "certs_not_well_configured: failed to create server config, not going to listen, error = %e"
+                error = %e,
+                "certs_not_well_configured: certificate and key don't make a pair, not going to listen"
             );
-            Err(TlsError::ConfigCreation(e.to_string()))
+            return Err(TlsError::ConfigCreation(e.to_string()));
+        }
+    };
+
+    let not_after = key
+        .end_entity_cert()
+        .ok()
+        .and_then(|cert| x509::not_after(cert));
+    match not_after {
+        Some(not_after) if not_after <= Utc::now() => {
+            warn!(server = server_name, not_after = %not_after, "tls_certificate_expired")
+        }
+        Some(not_after) => {
+            info!(server = server_name, not_after = %not_after, "tls_certificate_expiry")
+        }
+        None => warn!(server = server_name, "tls_certificate_expiry_unknown"),
+    }
+
+    Ok(Arc::new(key))
+}
+
+// what clients have to show, per `tls.client_auth`
+async fn client_verifier(
+    server_name: &str,
+    tls: Option<&ServerTlsConf>,
+) -> Result<Arc<dyn ClientCertVerifier>, TlsError> {
+    let Some(tls) = tls.filter(|tls| tls.client_auth != ClientAuth::Off) else {
+        return Ok(WebPkiClientVerifier::no_client_auth());
+    };
+    let ca = tls.client_ca.as_ref().ok_or(TlsError::ClientCaMissing)?;
+
+    let pem = tokio::fs::read(ca)
+        .await
+        .map_err(|e| TlsError::CertificateRead {
+            path: ca.display().to_string(),
+            source: e,
+        })?;
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(&pem) {
+        let added = cert.ok().and_then(|cert| roots.add(cert).ok());
+        if added.is_none() {
+            return Err(TlsError::InvalidCertificate {
+                path: ca.display().to_string(),
+            });
+        }
+    }
+
+    let mut crls = Vec::new();
+    for path in &tls.client_crls {
+        let pem = tokio::fs::read(path).await.map_err(|e| TlsError::CrlRead {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        for crl in CertificateRevocationListDer::pem_slice_iter(&pem) {
+            crls.push(crl.map_err(|_| TlsError::InvalidCrl {
+                path: path.display().to_string(),
+            })?);
         }
     }
+
+    info!(
+        server = server_name,
+        mode = ?tls.client_auth,
+        ca = %ca.display(),
+        roots = roots.len(),
+        crls = crls.len(),
+        "tls_client_auth"
+    );
+
+    // certificates whose issuer has no crl listed are not refused for it
+    let mut builder = WebPkiClientVerifier::builder(Arc::new(roots))
+        .with_crls(crls)
+        .allow_unknown_revocation_status();
+    if tls.client_auth == ClientAuth::Optional {
+        builder = builder.allow_unauthenticated();
+    }
+
+    builder
+        .build()
+        .map_err(|e| TlsError::ConfigCreation(e.to_string()))
+}
+
+fn build_server_config(
+    server_name: &str,
+    tls: Option<&ServerTlsConf>,
+    verifier: Arc<dyn ClientCertVerifier>,
+    resolver: Arc<SniResolver>,
+) -> Result<(rustls::ServerConfig, Option<Arc<KeyFileTicketer>>), TlsError> {
+    let provider = provider(server_name, tls)?;
+
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| TlsError::ConfigCreation(e.to_string()))?
+        .with_client_cert_verifier(verifier)
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let Some(tls) = tls else {
+        return Ok((config, None));
+    };
+
+    config.session_storage = match tls.session_cache_size {
+        0 => Arc::new(NoServerSessionStorage {}),
+        size => ServerSessionMemoryCache::new(size),
+    };
+
+    let mut tickets = None;
+    match (tls.session_tickets, &tls.ticket_key_file) {
+        (false, None) => {}
+        (false, Some(_)) => return Err(TlsError::TicketKeyUnused),
+        (true, None) => {
+            config.ticketer =
+                aws_lc_rs::Ticketer::new().map_err(|e| TlsError::ConfigCreation(e.to_string()))?;
+        }
+        (true, Some(path)) => {
+            let ticketer = Arc::new(KeyFileTicketer::load(path, tls.ticket_lifetime_secs)?);
+            config.ticketer = ticketer.clone();
+            tickets = Some(ticketer);
+        }
+    }
+
+    if tls.max_early_data_size > 0 {
+        if !tls.session_tickets && tls.session_cache_size == 0 {
+            return Err(TlsError::EarlyDataWithoutResumption);
+        }
+        // the only value quic allows besides 0 (rfc 9001 4.6.1)
+        config.max_early_data_size = u32::MAX;
+    }
+
+    info!(
+        server = server_name,
+        early_data = tls.max_early_data_size > 0,
+        session_tickets = tls.session_tickets,
+        shared_ticket_keys = tls.ticket_key_file.is_some(),
+        session_cache_size = tls.session_cache_size,
+        "tls_sessions"
+    );
+
+    Ok((config, tickets))
+}
+
+// the default provider narrowed to the suites and groups `tls` allows, in its
+// order; anything quic can't run with is refused here rather than at the first handshake
+fn provider(server_name: &str, tls: Option<&ServerTlsConf>) -> Result<CryptoProvider, TlsError> {
+    let mut provider = aws_lc_rs::default_provider();
+    provider
+        .cipher_suites
+        .retain(|suite| suite.version() == &rustls::version::TLS13);
+
+    let Some(tls) = tls else {
+        return Ok(provider);
+    };
+
+    if tls.versions.contains(&TlsVersion::Tls12) {
+        return Err(TlsError::QuicIncompatible(
+            "tls 1.2 can't carry quic, versions may only list \"1.3\"".into(),
+        ));
+    }
+    if !tls.versions.contains(&TlsVersion::Tls13) {
+        return Err(TlsError::QuicIncompatible(
+            "versions has to include \"1.3\"".into(),
+        ));
+    }
+
+    if !tls.cipher_suites.is_empty() {
+        let mut suites = Vec::new();
+        for name in &tls.cipher_suites {
+            let suite = aws_lc_rs::ALL_CIPHER_SUITES
+                .iter()
+                .find(|suite| suite.suite().as_str() == Some(name.as_str()))
+                .ok_or_else(|| TlsError::UnknownCipherSuite(name.clone()))?;
+            if suite.version() != &rustls::version::TLS13 {
+                return Err(TlsError::QuicIncompatible(format!(
+                    "{name} is a tls 1.2 suite"
+                )));
+            }
+            suites.push(*suite);
+        }
+        if !tls
+            .cipher_suites
+            .iter()
+            .any(|name| name == QUIC_INITIAL_SUITE)
+        {
+            return Err(TlsError::QuicIncompatible(format!(
+                "cipher_suites has to include {QUIC_INITIAL_SUITE}"
+            )));
+        }
+        provider.cipher_suites = suites;
+    }
+
+    if !tls.kx_groups.is_empty() {
+        let mut groups = Vec::new();
+        for name in &tls.kx_groups {
+            let group = aws_lc_rs::ALL_KX_GROUPS
+                .iter()
+                .find(|group| {
+                    group
+                        .name()
+                        .as_str()
+                        .is_some_and(|n| n.eq_ignore_ascii_case(name))
+                })
+                .ok_or_else(|| TlsError::UnknownKxGroup(name.clone()))?;
+            groups.push(*group);
+        }
+        provider.kx_groups = groups;
+    }
+
+    info!(
+        server = server_name,
+        cipher_suites = ?provider.cipher_suites.iter().map(|s| s.suite()).collect::<Vec<_>>(),
+        kx_groups = ?provider.kx_groups.iter().map(|g| g.name()).collect::<Vec<_>>(),
+        "tls_policy"
+    );
+
+    Ok(provider)
 }
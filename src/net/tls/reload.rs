@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use super::{KeyFileTicketer, SniResolver, load_certs};
+use crate::config::{AppConfig, Server, ServerTlsConf};
+
+/// keep the certificates `resolver` hands out, and the keys of `tickets`, in
+/// step with the files of `server_name` and its virtual hosts: checked every
+/// `reload_interval_secs`, and on SIGUSR1. connections already up keep theirs;
+/// None when there are no tls files.
+pub fn spawn(
+    config: Arc<AppConfig>,
+    server_name: String,
+    resolver: Arc<SniResolver>,
+    tickets: Option<Arc<KeyFileTicketer>>,
+) -> Option<JoinHandle<()>> {
+    if files(config.servers.get(&server_name)?).is_empty() {
+        return None;
+    }
+    Some(tokio::spawn(run(config, server_name, resolver, tickets)))
+}
+
+async fn run(
+    config: Arc<AppConfig>,
+    server_name: String,
+    resolver: Arc<SniResolver>,
+    tickets: Option<Arc<KeyFileTicketer>>,
+) {
+    let Some(server) = config.servers.get(&server_name) else {
+        return;
+    };
+    // the server's setting; with only virtual hosts on tls, the default
+    let interval_secs = server
+        .tls
+        .as_ref()
+        .map_or_else(ServerTlsConf::default_reload_interval_secs, |tls| {
+            tls.reload_interval_secs
+        });
+
+    let mut usr1 = match signal(SignalKind::user_defined1()) {
+        Ok(usr1) => Some(usr1),
+        Err(e) => {
+            warn!(server = %server_name, error = %e, "tls_reload_signal_unavailable");
+            None
+        }
+    };
+
+    if usr1.is_none() && interval_secs == 0 {
+        return;
+    }
+
+    // 0 turns polling off, only the signal reloads then
+    let mut interval = time::interval(Duration::from_secs(interval_secs.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.tick().await;
+
+    let ticket_file = tickets.as_ref().map(|tickets| tickets.path().to_path_buf());
+    let mut seen = stamps(server).await;
+    let mut seen_tickets = stamp(ticket_file.as_deref()).await;
+
+    loop {
+        let (certs_changed, tickets_changed) = tokio::select! {
+            _ = interval.tick(), if interval_secs > 0 => {
+                let current = stamps(server).await;
+                let current_tickets = stamp(ticket_file.as_deref()).await;
+                let changed = (current != seen, current_tickets != seen_tickets);
+                if changed == (false, false) {
+                    continue;
+                }
+                debug!(server = %server_name, "tls_files_changed");
+                seen = current;
+                seen_tickets = current_tickets;
+                changed
+            }
+            Some(()) = async { usr1.as_mut()?.recv().await } => {
+                info!(server = %server_name, "tls_reload_signal_received");
+                seen = stamps(server).await;
+                seen_tickets = stamp(ticket_file.as_deref()).await;
+                (true, true)
+            }
+        };
+
+        // a half written pair fails to load; the old certificates stay until it's complete
+        if certs_changed {
+            match load_certs(&config, &server_name, server).await {
+                Ok(certs) => {
+                    resolver.swap(certs);
+                    info!(server = %server_name, "tls_reloaded");
+                }
+                Err(e) => warn!(server = %server_name, error = %e, "tls_reload_rejected"),
+            }
+        }
+
+        // likewise a half written key file, it's tried again on the next change
+        if tickets_changed
+            && let Some(tickets) = &tickets
+            && let Err(e) = tickets.reload().await
+        {
+            warn!(server = %server_name, error = %e, "tls_ticket_keys_rejected");
+        }
+    }
+}
+
+// modification time and size of every certificate and key file of `server`
+async fn stamps(server: &Server) -> Vec<Option<(SystemTime, u64)>> {
+    let mut stamps = Vec::new();
+    for path in files(server) {
+        stamps.push(stamp(Some(&path)).await);
+    }
+    stamps
+}
+
+async fn stamp(path: Option<&Path>) -> Option<(SystemTime, u64)> {
+    let meta = tokio::fs::metadata(path?).await.ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+fn files(server: &Server) -> Vec<PathBuf> {
+    server
+        .tls
+        .iter()
+        .chain(server.hosts.values().filter_map(|vhost| vhost.tls.as_ref()))
+        .flat_map(|tls| {
+            std::iter::once((&tls.cert, &tls.key))
+                .chain(tls.certificates.iter().map(|c| (&c.cert, &c.key)))
+        })
+        .flat_map(|(cert, key)| [cert.clone(), key.clone()])
+        .collect()
+}
@@ -0,0 +1,76 @@
+use std::sync::{Arc, RwLock};
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tracing::debug;
+
+use crate::config::tls::SniFallback;
+use crate::helpers::host;
+
+/// the certificates of one server
+#[derive(Debug)]
+pub struct Certs {
+    /// by sni name; `*.example.com` covers one label
+    pub names: Vec<(String, Arc<CertifiedKey>)>,
+    pub fallback: Arc<CertifiedKey>,
+}
+
+/// picks certificates by sni, matching names the way `http::vhost` picks hosts.
+/// handshakes in progress keep the certificates they started with when they are swapped.
+#[derive(Debug)]
+pub struct SniResolver {
+    certs: RwLock<Arc<Certs>>,
+    missing: SniFallback,
+    unknown: SniFallback,
+}
+
+impl SniResolver {
+    pub fn new(certs: Certs, missing: SniFallback, unknown: SniFallback) -> Self {
+        Self {
+            certs: RwLock::new(Arc::new(certs)),
+            missing,
+            unknown,
+        }
+    }
+
+    /// certificates for handshakes from now on
+    pub fn swap(&self, certs: Certs) {
+        *self.certs.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(certs);
+    }
+
+    fn current(&self) -> Arc<Certs> {
+        self.certs.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let certs = self.current();
+        let fall_back = |policy| match policy {
+            SniFallback::Default => Some(certs.fallback.clone()),
+            SniFallback::Reject => None,
+        };
+
+        let Some(name) = hello.server_name() else {
+            debug!(policy = ?self.missing, "tls_sni_missing");
+            return fall_back(self.missing);
+        };
+
+        let name = host::normalize(name);
+        match host::find(
+            certs.names.iter().map(|(pattern, _)| pattern.as_str()),
+            &name,
+        ) {
+            // the first listed wins among equal names
+            Some(pattern) => certs
+                .names
+                .iter()
+                .find(|(listed, _)| listed == pattern)
+                .map(|(_, key)| key.clone()),
+            None => {
+                debug!(sni = %name, policy = ?self.unknown, "tls_sni_unknown");
+                fall_back(self.unknown)
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use aws_lc_rs::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use aws_lc_rs::digest::{SHA256, digest};
+use aws_lc_rs::rand::{SecureRandom, SystemRandom};
+use rustls::server::ProducesTickets;
+use tracing::info;
+
+use super::error::TlsError;
+
+// tickets start with this much of the sha-256 of the key that sealed them
+const KEY_ID_LEN: usize = 8;
+
+/// seals session tickets with keys from a file every instance behind an
+/// address shares, so any of them can resume a session another one started.
+/// `reload` brings in a changed file.
+pub struct KeyFileTicketer {
+    path: PathBuf,
+    lifetime: u32,
+    rng: SystemRandom,
+    // the first one seals
+    keys: RwLock<Arc<Vec<TicketKey>>>,
+}
+
+struct TicketKey {
+    id: [u8; KEY_ID_LEN],
+    key: LessSafeKey,
+}
+
+impl KeyFileTicketer {
+    pub fn load(path: &Path, lifetime: u32) -> Result<Self, TlsError> {
+        let text = std::fs::read_to_string(path).map_err(|e| read_error(path, e))?;
+        let keys = parse_keys(path, &text)?;
+        info!(path = %path.display(), keys = keys.len(), "tls_ticket_keys_loaded");
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            lifetime,
+            rng: SystemRandom::new(),
+            keys: RwLock::new(Arc::new(keys)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// re-read the key file; a file that doesn't parse leaves the keys in use
+    pub async fn reload(&self) -> Result<(), TlsError> {
+        let text = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| read_error(&self.path, e))?;
+        let keys = parse_keys(&self.path, &text)?;
+        info!(path = %self.path.display(), keys = keys.len(), "tls_ticket_keys_reloaded");
+
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keys);
+        Ok(())
+    }
+
+    fn keys(&self) -> Arc<Vec<TicketKey>> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ProducesTickets for KeyFileTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime
+    }
+
+    // key id, nonce, then the sealed session
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let keys = self.keys();
+        let key = keys.first()?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).ok()?;
+
+        let mut sealed = plain.to_vec();
+        key.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key.id),
+                &mut sealed,
+            )
+            .ok()?;
+
+        let mut ticket = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + sealed.len());
+        ticket.extend_from_slice(&key.id);
+        ticket.extend_from_slice(&nonce);
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let (id, rest) = cipher.split_first_chunk::<KEY_ID_LEN>()?;
+        let (nonce, sealed) = rest.split_first_chunk::<NONCE_LEN>()?;
+
+        let keys = self.keys();
+        let key = keys.iter().find(|key| key.id == *id)?;
+
+        let mut sealed = sealed.to_vec();
+        let plain = key
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(*nonce),
+                Aad::from(*id),
+                &mut sealed,
+            )
+            .ok()?;
+        Some(plain.to_vec())
+    }
+}
+
+impl fmt::Debug for KeyFileTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyFileTicketer")
+            .field("path", &self.path)
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+fn read_error(path: &Path, source: std::io::Error) -> TlsError {
+    TlsError::TicketKeyRead {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+fn parse_keys(path: &Path, text: &str) -> Result<Vec<TicketKey>, TlsError> {
+    let invalid = || TlsError::InvalidTicketKey {
+        path: path.display().to_string(),
+    };
+
+    let mut keys = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let secret = hex_key(line).ok_or_else(invalid)?;
+
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&digest(&SHA256, &secret).as_ref()[..KEY_ID_LEN]);
+        let key = UnboundKey::new(&AES_256_GCM, &secret).map_err(|_| invalid())?;
+
+        keys.push(TicketKey {
+            id,
+            key: LessSafeKey::new(key),
+        });
+    }
+
+    if keys.is_empty() {
+        return Err(invalid());
+    }
+    Ok(keys)
+}
+
+fn hex_key(line: &str) -> Option<[u8; 32]> {
+    if line.len() != 64 {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    let mut digits = line.chars().map(|c| c.to_digit(16));
+    for byte in &mut key {
+        let (high, low) = (digits.next()??, digits.next()??);
+        *byte = u8::try_from(high << 4 | low).ok()?;
+    }
+    Some(key)
+}
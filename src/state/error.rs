@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error(transparent)]
+    Router(#[from] crate::http::router::error::RouterError),
+
+    #[error("invalid header rule '{header}' in {place}: {reason}")]
+    InvalidHeaderRule {
+        place: String,
+        header: String,
+        reason: &'static str,
+    },
+
+    #[cfg(feature = "proxy")]
+    #[error(transparent)]
+    Proxy(#[from] crate::features::proxy::ProxyError),
+}
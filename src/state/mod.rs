@@ -0,0 +1,183 @@
+pub mod error;
+
+use std::collections::HashMap;
+use std::iter;
+
+use crate::config::{AppConfig, Server};
+use crate::helpers::mime;
+use crate::http::headers;
+use crate::http::router::{self, Router, error::RouterError};
+use crate::state::error::StateError;
+
+/// runtime state shared by every server. unlike `AppConfig` it changes while
+/// serving, and it is rebuilt from the config on every (re)start.
+pub struct State {
+    /// compiled routes, by server name
+    pub routers: HashMap<String, Routers>,
+
+    pub mime_types: mime::Types,
+
+    #[cfg(feature = "proxy")]
+    pub upstreams: crate::features::proxy::Upstreams,
+
+    #[cfg(feature = "caching")]
+    pub cache: crate::features::caching::Cache,
+
+    #[cfg(feature = "scripting")]
+    pub scripts: crate::features::scripting::Scripts,
+
+    #[cfg(feature = "acme")]
+    pub acme: crate::features::acme::Acme,
+
+    #[cfg(feature = "ratelimit")]
+    pub rate_limiter: crate::features::ratelimit::RateLimiter,
+}
+
+impl State {
+    pub fn new(config: &AppConfig) -> Result<Self, StateError> {
+        for (name, server) in &config.servers {
+            check_header_rules(name, server)?;
+        }
+
+        let routers = config
+            .servers
+            .iter()
+            .map(|(name, server)| Ok((name.clone(), Routers::new(name, server)?)))
+            .collect::<Result<_, RouterError>>()?;
+
+        #[cfg(feature = "proxy")]
+        let upstreams = crate::features::proxy::Upstreams::new(&config.upstreams)?;
+        #[cfg(feature = "proxy")]
+        for server in config.servers.values() {
+            check_upstreams(server, &upstreams)?;
+        }
+
+        Ok(Self {
+            routers,
+            mime_types: mime::Types::new(&config.mime_types),
+
+            #[cfg(feature = "proxy")]
+            upstreams,
+
+            #[cfg(feature = "caching")]
+            cache: crate::features::caching::Cache::new(&config.cache),
+
+            #[cfg(feature = "scripting")]
+            scripts: crate::features::scripting::Scripts::new(&config.scripting),
+
+            #[cfg(feature = "acme")]
+            acme: crate::features::acme::Acme::new(config),
+
+            #[cfg(feature = "ratelimit")]
+            rate_limiter: crate::features::ratelimit::RateLimiter::new(&config.rate_limits),
+        })
+    }
+}
+
+// header rules of `server` and its routes, so a typo stops the start instead
+// of doing nothing on every request
+fn check_header_rules(name: &str, server: &Server) -> Result<(), StateError> {
+    let routes = server
+        .routes
+        .iter()
+        .map(|(key, route)| (format!("route '{key}' of server '{name}'"), route))
+        .chain(server.hosts.iter().flat_map(|(host, vhost)| {
+            vhost
+                .routes
+                .iter()
+                .map(move |(key, route)| (format!("route '{key}' of host '{host}'"), route))
+        }));
+    let rules = [
+        (format!("server '{name}'"), &server.headers),
+        (format!("server '{name}'"), &server.upstream_headers),
+    ]
+    .into_iter()
+    .chain(routes.flat_map(|(place, route)| {
+        [
+            (place.clone(), &route.headers),
+            (place, &route.upstream_headers),
+        ]
+    }));
+
+    for (place, rules) in rules {
+        headers::check_rules(rules).map_err(|e| StateError::InvalidHeaderRule {
+            place,
+            header: e.header,
+            reason: e.reason,
+        })?;
+    }
+    Ok(())
+}
+
+// every proxy action of `server` names a group or an http:// uri, so a typo
+// stops the start instead of failing each request
+#[cfg(feature = "proxy")]
+fn check_upstreams(
+    server: &Server,
+    upstreams: &crate::features::proxy::Upstreams,
+) -> Result<(), StateError> {
+    use crate::config::Action;
+
+    let routes = server.routes.values().chain(
+        server
+            .hosts
+            .values()
+            .flat_map(|vhost| vhost.routes.values()),
+    );
+    let standards = iter::once(&server.standard)
+        .chain(server.hosts.values().filter_map(|h| h.standard.as_ref()));
+    let actions = routes
+        .flat_map(|route| route.methods.values())
+        .chain(standards.flat_map(|standard| standard.actions().map(|(_, action)| action)));
+
+    for action in actions {
+        if let Action::Proxy { upstream, .. } = action {
+            upstreams.check(upstream)?;
+        }
+    }
+    Ok(())
+}
+
+/// compiled routes of one server and of each of its virtual hosts
+pub struct Routers {
+    server: Router,
+    hosts: HashMap<String, Router>,
+}
+
+impl Routers {
+    fn new(name: &str, server: &Server) -> Result<Self, RouterError> {
+        if let Some(default) = &server.default_host
+            && !server.hosts.contains_key(default)
+        {
+            return Err(RouterError::UnknownDefaultHost {
+                server: name.to_string(),
+                host: default.clone(),
+            });
+        }
+
+        let standards = iter::once(&server.standard)
+            .chain(server.hosts.values().filter_map(|h| h.standard.as_ref()));
+        for standard in standards {
+            for (response, action) in standard.actions() {
+                router::check_action(&format!("standard.{response}"), action)?;
+            }
+        }
+
+        Ok(Self {
+            server: Router::new(&server.routes)?,
+            hosts: server
+                .hosts
+                .iter()
+                .map(|(host, vhost)| Ok((host.clone(), Router::new(&vhost.routes)?)))
+                .collect::<Result<_, RouterError>>()?,
+        })
+    }
+
+    /// router of the virtual host `host`, or of the server itself for None
+    pub fn get(&self, host: Option<&str>) -> Option<&Router> {
+        match host {
+            Some(host) => self.hosts.get(host),
+            None => Some(&self.server),
+        }
+    }
+}